@@ -0,0 +1,41 @@
+-- Initial schema. Written to also adopt databases which were set up by hand before migrations existed.
+DO $$ BEGIN
+    CREATE TYPE registration AS ENUM ('open', 'invite', 'closed');
+EXCEPTION
+    WHEN duplicate_object THEN null;
+END $$;
+
+CREATE TABLE IF NOT EXISTS servers (
+    name TEXT NOT NULL,
+    url TEXT NOT NULL UNIQUE,
+    server_name TEXT PRIMARY KEY,
+    logo_url TEXT,
+    admins TEXT[] NOT NULL,
+    categories TEXT[] NOT NULL,
+    rules TEXT NOT NULL,
+    description TEXT NOT NULL,
+    registration_status registration NOT NULL,
+    verified BOOLEAN NOT NULL DEFAULT false
+);
+
+ALTER TABLE servers ADD COLUMN IF NOT EXISTS registered_by TEXT;
+UPDATE servers SET registered_by = admins[1] WHERE registered_by IS NULL;
+ALTER TABLE servers ALTER COLUMN registered_by SET NOT NULL;
+ALTER TABLE servers ADD COLUMN IF NOT EXISTS verified_by TEXT;
+ALTER TABLE servers ADD COLUMN IF NOT EXISTS verified_at TIMESTAMPTZ;
+ALTER TABLE servers ADD COLUMN IF NOT EXISTS created_at TIMESTAMPTZ NOT NULL DEFAULT now();
+ALTER TABLE servers ADD COLUMN IF NOT EXISTS updated_at TIMESTAMPTZ NOT NULL DEFAULT now();
+
+CREATE TABLE IF NOT EXISTS servers_categories (
+    server_url TEXT NOT NULL REFERENCES servers (url) ON UPDATE CASCADE ON DELETE CASCADE,
+    category_name TEXT NOT NULL,
+    PRIMARY KEY (server_url, category_name)
+);
+
+CREATE TABLE IF NOT EXISTS rejections (
+    id SERIAL PRIMARY KEY,
+    server_name TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    rejected_by TEXT NOT NULL,
+    rejected_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
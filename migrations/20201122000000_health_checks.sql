@@ -0,0 +1,4 @@
+ALTER TABLE servers ADD COLUMN last_checked_at TIMESTAMPTZ;
+ALTER TABLE servers ADD COLUMN last_ok_at TIMESTAMPTZ;
+ALTER TABLE servers ADD COLUMN consecutive_failures INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE servers ADD COLUMN healthy BOOLEAN NOT NULL DEFAULT true;
@@ -0,0 +1 @@
+ALTER TABLE servers ADD COLUMN logo_mxc TEXT;
@@ -0,0 +1,3 @@
+ALTER TABLE servers ADD COLUMN check_report TEXT;
+ALTER TABLE servers ADD COLUMN claimed_by TEXT;
+ALTER TABLE servers ADD COLUMN claimed_at TIMESTAMPTZ;
@@ -0,0 +1,2 @@
+ALTER TABLE servers ADD COLUMN review_event_id TEXT;
+CREATE INDEX servers_review_event_id ON servers (review_event_id);
@@ -0,0 +1,8 @@
+CREATE TYPE role AS ENUM ('read-only', 'reviewer', 'moderator', 'owner');
+
+CREATE TABLE roles (
+    mxid TEXT PRIMARY KEY,
+    role role NOT NULL,
+    granted_by TEXT NOT NULL,
+    granted_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
@@ -0,0 +1,2 @@
+-- Fields the last health check found out of date, so the admin room is only told when they change
+ALTER TABLE servers ADD COLUMN outdated_fields TEXT[] NOT NULL DEFAULT '{}';
@@ -0,0 +1,231 @@
+use crate::config::Config;
+use crate::database::models::{Registration, Server};
+use crate::database::{get_database_pool, servers};
+use serde::{Deserialize, Serialize};
+use sqlx::types::chrono::{DateTime, Utc};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use tracing::*;
+use warp::http::{header, Response, StatusCode};
+use warp::{Filter, Rejection, Reply};
+
+/// The public part of a `Server`
+#[derive(Serialize, Clone)]
+pub struct ServerListing {
+    pub name: String,
+    pub url: String,
+    pub server_name: String,
+    pub logo_url: Option<String>,
+    pub logo_mxc: Option<String>,
+    pub admins: Vec<String>,
+    pub categories: Vec<String>,
+    pub rules: String,
+    pub description: String,
+    pub registration_status: Registration,
+    pub verified_at: Option<DateTime<Utc>>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<Server> for ServerListing {
+    fn from(server: Server) -> Self {
+        ServerListing {
+            name: server.name,
+            url: server.url,
+            server_name: server.server_name,
+            logo_url: server.logo_url,
+            logo_mxc: server.logo_mxc,
+            admins: server.admins,
+            categories: server.categories,
+            rules: server.rules,
+            description: server.description,
+            registration_status: server.registration_status,
+            verified_at: server.verified_at,
+            updated_at: server.updated_at,
+        }
+    }
+}
+
+impl ServerListing {
+    /// The last time anything visible about the listing changed
+    pub fn last_modified(&self) -> DateTime<Utc> {
+        self.verified_at.map_or(self.updated_at, |verified_at| {
+            verified_at.max(self.updated_at)
+        })
+    }
+}
+
+#[derive(Serialize)]
+struct Category {
+    name: String,
+    servers: i64,
+}
+
+#[derive(Deserialize)]
+struct ServerFilter {
+    category: Option<String>,
+    registration_status: Option<Registration>,
+}
+
+/// Headers of conditional requests
+struct Conditions {
+    if_none_match: Option<String>,
+    if_modified_since: Option<String>,
+}
+
+/// Serves the read-only JSON API if `api_listen_address` is configured
+pub async fn serve(config: Config<'static>) {
+    // Invalid addresses were already reported by `config::validate`
+    let address = match config.api_listen_address() {
+        Ok(Some(address)) => address,
+        _ => return,
+    };
+
+    let with_config = warp::any().map(move || config.clone());
+    let conditions = warp::header::optional::<String>("if-none-match")
+        .and(warp::header::optional::<String>("if-modified-since"))
+        .map(|if_none_match, if_modified_since| Conditions {
+            if_none_match,
+            if_modified_since,
+        });
+
+    let list_servers = warp::path!("servers")
+        .and(warp::query::<ServerFilter>())
+        .and(conditions.clone())
+        .and(with_config.clone())
+        .and_then(list_servers);
+    let get_server = warp::path!("servers" / String)
+        .and(conditions.clone())
+        .and(with_config.clone())
+        .and_then(get_server);
+    let list_categories = warp::path!("categories")
+        .and(conditions)
+        .and(with_config)
+        .and_then(list_categories);
+
+    let routes = warp::get().and(list_servers.or(get_server).or(list_categories));
+
+    match warp::serve(routes).try_bind_ephemeral(address) {
+        Ok((address, server)) => {
+            info!("Serving API on {}", address);
+            server.await;
+        }
+        Err(e) => error!("Unable to serve the API on {}: {}", address, e),
+    }
+}
+
+async fn list_servers(
+    filter: ServerFilter,
+    conditions: Conditions,
+    config: Config<'static>,
+) -> Result<warp::reply::Response, Rejection> {
+    let database = match get_database_pool(config).await {
+        Ok(database) => database,
+        Err(e) => return Ok(internal_error(e)),
+    };
+    match servers::verified(
+        &database,
+        filter.category.as_deref(),
+        filter.registration_status,
+    )
+    .await
+    {
+        Ok(servers) => {
+            // Removed servers do not show up in the timestamps of the remaining ones, so only the ETag is used
+            let listings: Vec<ServerListing> = servers.into_iter().map(Into::into).collect();
+            Ok(cached_json(&listings, None, conditions))
+        }
+        Err(e) => Ok(internal_error(e)),
+    }
+}
+
+async fn get_server(
+    server_name: String,
+    conditions: Conditions,
+    config: Config<'static>,
+) -> Result<warp::reply::Response, Rejection> {
+    let database = match get_database_pool(config).await {
+        Ok(database) => database,
+        Err(e) => return Ok(internal_error(e)),
+    };
+    match servers::verified_by_name(&database, &server_name).await {
+        Ok(Some(server)) => {
+            let listing = ServerListing::from(server);
+            let last_modified = listing.last_modified();
+            Ok(cached_json(&listing, Some(last_modified), conditions))
+        }
+        Ok(None) => Err(warp::reject::not_found()),
+        Err(e) => Ok(internal_error(e)),
+    }
+}
+
+async fn list_categories(
+    conditions: Conditions,
+    config: Config<'static>,
+) -> Result<warp::reply::Response, Rejection> {
+    let database = match get_database_pool(config).await {
+        Ok(database) => database,
+        Err(e) => return Ok(internal_error(e)),
+    };
+    match servers::categories(&database).await {
+        Ok(categories) => {
+            let categories: Vec<Category> = categories
+                .into_iter()
+                .map(|(name, servers)| Category { name, servers })
+                .collect();
+            Ok(cached_json(&categories, None, conditions))
+        }
+        Err(e) => Ok(internal_error(e)),
+    }
+}
+
+/// Serializes the value and answers with 304 if the client already has the current version
+fn cached_json<T: Serialize>(
+    value: &T,
+    last_modified: Option<DateTime<Utc>>,
+    conditions: Conditions,
+) -> warp::reply::Response {
+    let body = match serde_json::to_string(value) {
+        Ok(body) => body,
+        Err(e) => return internal_error(e),
+    };
+    let mut hasher = DefaultHasher::new();
+    body.hash(&mut hasher);
+    let etag = format!("\"{:x}\"", hasher.finish());
+    let not_modified = match conditions.if_none_match {
+        Some(ref if_none_match) => if_none_match
+            .split(',')
+            .any(|x| x.trim() == etag || x.trim() == "*"),
+        None => match (conditions.if_modified_since, last_modified) {
+            (Some(ref if_modified_since), Some(last_modified)) => {
+                DateTime::parse_from_rfc2822(if_modified_since)
+                    .map(|since| last_modified.timestamp() <= since.timestamp())
+                    .unwrap_or(false)
+            }
+            _ => false,
+        },
+    };
+    let last_modified = last_modified.map(|x| x.format("%a, %d %b %Y %H:%M:%S GMT").to_string());
+
+    let mut response = Response::builder().header(header::ETAG, etag.as_str());
+    if let Some(ref last_modified) = last_modified {
+        response = response.header(header::LAST_MODIFIED, last_modified.as_str());
+    }
+    let response = if not_modified {
+        response
+            .status(StatusCode::NOT_MODIFIED)
+            .body(String::new())
+    } else {
+        response
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(body)
+    };
+    match response {
+        Ok(response) => response.into_response(),
+        Err(e) => internal_error(e),
+    }
+}
+
+fn internal_error<E: std::fmt::Display>(e: E) -> warp::reply::Response {
+    error!("API request failed: {}", e);
+    StatusCode::INTERNAL_SERVER_ERROR.into_response()
+}
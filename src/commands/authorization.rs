@@ -0,0 +1,80 @@
+//! Who may run which command. The dispatcher checks the declared permission in every room before a command runs.
+use crate::config::Config;
+use crate::database::{get_database_pool, models::Role, roles};
+use crate::errors::Error;
+use matrix_sdk::identifiers::UserId;
+use sqlx::postgres::PgPool;
+use tracing::*;
+
+/// Who is allowed to run a command. Every command module declares it as `PERMISSION`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Permission {
+    Everyone,
+    /// Requires at least this role
+    Role(Role),
+}
+
+/// Outcome of checking a permission
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Decision {
+    Granted,
+    Denied {
+        required: Role,
+        actual: Option<Role>,
+    },
+}
+
+/// The role of the user. Admins listed in the config are always owners.
+pub(crate) async fn role_of<'a>(
+    config: &Config<'a>,
+    database: &PgPool,
+    user_id: &UserId,
+) -> Result<Option<Role>, Error> {
+    if config.is_admin(user_id) {
+        return Ok(Some(Role::Owner));
+    }
+    roles::role_of(database, user_id.as_str()).await
+}
+
+/// Checks if the user has the permission
+pub(crate) async fn authorize(
+    config: &Config<'static>,
+    user_id: &UserId,
+    permission: Permission,
+) -> Result<Decision, Error> {
+    let required = match permission {
+        Permission::Everyone => return Ok(Decision::Granted),
+        Permission::Role(required) => required,
+    };
+
+    let database = get_database_pool(config.clone()).await?;
+    let actual = role_of(config, &database, user_id).await?;
+    if actual >= Some(required) {
+        Ok(Decision::Granted)
+    } else {
+        Ok(Decision::Denied { required, actual })
+    }
+}
+
+/// Logs the denied attempt and returns the error message for the user.
+///
+/// The room is part of the span the dispatcher runs commands in.
+pub(crate) fn deny(user_id: &UserId, action: &str, required: Role, actual: Option<Role>) -> String {
+    warn!(
+        user = %user_id,
+        required = required.name(),
+        actual = actual.map_or("none", |x| x.name()),
+        "Denied {}",
+        action
+    );
+
+    format!(
+        "[ERROR] Permission denied: {} requires the {} role and you have {}. Ask an owner of the keymaker project to grant it.",
+        action,
+        required.name(),
+        actual.map_or_else(
+            || "no role".to_string(),
+            |x| format!("the {} role", x.name())
+        )
+    )
+}
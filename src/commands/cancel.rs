@@ -0,0 +1,88 @@
+use super::Permission;
+use crate::extensions::ClientExt;
+use crate::{config::Config, database::get_database_pool, errors::Error};
+use matrix_sdk::{
+    events::{room::message::MessageEventContent, AnyMessageEventContent},
+    identifiers::user_id::UserId,
+};
+use mrsbfh::commands::command;
+use std::convert::TryFrom;
+
+pub(super) const PERMISSION: Permission = Permission::Everyone;
+
+#[command(
+    help = "`!cancel` - Withdraw the pending registration of your server. You need to be listed as server admin for this."
+)]
+pub async fn cancel<'a>(
+    matrix_client: matrix_sdk::Client,
+    mut tx: mrsbfh::Sender,
+    config: Config<'a>,
+    sender: String,
+    mut _args: Vec<&str>,
+) -> Result<(), Error>
+where
+    Config<'a>: mrsbfh::config::Loader + Clone,
+{
+    let sender_id_typed = UserId::try_from(sender.clone()).unwrap();
+    let server = sender_id_typed.server_name().as_str();
+
+    let database = get_database_pool(config.clone()).await?;
+
+    let pending_server = sqlx::query!(
+        r#"SELECT url, admins FROM servers WHERE server_name = $1 AND verified = false"#,
+        server
+    )
+    .fetch_optional(&database)
+    .await?;
+
+    let pending_server = match pending_server {
+        Some(record) => record,
+        None => {
+            let content =
+                AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(format!(
+                    "[ERROR] There is no pending registration for '{}'.",
+                    server
+                )));
+            tx.send(content).await?;
+            return Ok(());
+        }
+    };
+
+    if !pending_server.admins.iter().any(|x| x == &sender) {
+        let content =
+            AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(format!(
+                "[ERROR] You are not listed as an admin of '{}'. Admins of the pending registration: {:?}",
+                server, pending_server.admins
+            )));
+        tx.send(content).await?;
+        return Ok(());
+    }
+
+    let mut transaction = database.begin().await?;
+    sqlx::query!(
+        r#"DELETE FROM servers_categories WHERE server_url = $1"#,
+        pending_server.url
+    )
+    .execute(&mut transaction)
+    .await?;
+    sqlx::query!(
+        r#"DELETE FROM servers WHERE server_name = $1 AND verified = false"#,
+        server
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+
+    let content = AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(
+        "Your registration was withdrawn. You can register again at any time using `!register`.",
+    ));
+    tx.send(content).await?;
+
+    let content = AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(format!(
+        "Registration of {} was withdrawn by {}",
+        server, sender
+    )));
+    matrix_client.send_to_admin_room(&config, content).await;
+
+    Ok(())
+}
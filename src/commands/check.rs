@@ -0,0 +1,83 @@
+use super::Permission;
+use crate::database::models::Role;
+use crate::validation::{self, Validation};
+use crate::{config::Config, database::get_database_pool, errors::Error};
+use matrix_sdk::{
+    events::{room::message::MessageEventContent, AnyMessageEventContent},
+    identifiers::user_id::UserId,
+};
+use mrsbfh::commands::command;
+use std::convert::TryFrom;
+
+pub(super) const PERMISSION: Permission = Permission::Everyone;
+
+#[command(
+    help = "`!check [server]` - Run the automated tests against your .well-known file without registering. Moderators can check any server."
+)]
+pub async fn check<'a>(
+    _matrix_client: matrix_sdk::Client,
+    mut tx: mrsbfh::Sender,
+    config: Config<'a>,
+    sender: String,
+    args: Vec<&str>,
+) -> Result<(), Error>
+where
+    Config<'a>: mrsbfh::config::Loader + Clone,
+{
+    let sender_id_typed = UserId::try_from(sender.clone()).unwrap();
+    let own_server = sender_id_typed.server_name().as_str();
+    let server = args.first().copied().unwrap_or(own_server);
+
+    if server != own_server {
+        let database = get_database_pool(config.clone()).await?;
+        let role = super::role_of(&config, &database, &sender_id_typed).await?;
+        if role < Some(Role::Moderator) {
+            let message = super::authorization::deny(
+                &sender_id_typed,
+                &format!("Checking servers other than your own ({})", own_server),
+                Role::Moderator,
+                role,
+            );
+            let content =
+                AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(message));
+            tx.send(content).await?;
+            return Ok(());
+        }
+    }
+
+    let content = AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(format!(
+        "Checking {}...",
+        server
+    )));
+    tx.send(content).await?;
+
+    // Moderators checking foreign servers are not expected to be listed in them
+    let expected_admin = if server == own_server {
+        Some(sender.as_str())
+    } else {
+        None
+    };
+    let report = Validation::new(
+        validation::http_client(&config),
+        &config,
+        server,
+        expected_admin,
+    )
+    .run()
+    .await;
+
+    let summary = if report.passed() {
+        "All automated tests passed. Run `!register` to submit your server."
+    } else {
+        "Some automated tests failed. Fix the issues above and run `!check` again."
+    };
+    let content = AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(format!(
+        "Results for {}:\n{}\n\n{}",
+        report.well_known_url,
+        report.checklist(),
+        summary
+    )));
+    tx.send(content).await?;
+
+    Ok(())
+}
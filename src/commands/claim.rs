@@ -0,0 +1,104 @@
+use super::Permission;
+use crate::database::models::{Role, Server};
+use crate::{config::Config, database::get_database_pool, errors::Error};
+use matrix_sdk::events::{room::message::MessageEventContent, AnyMessageEventContent};
+use mrsbfh::commands::command;
+use sqlx::types::chrono::{Duration, Utc};
+
+pub(super) const PERMISSION: Permission = Permission::Role(Role::Reviewer);
+
+/// Days after which a claim no longer keeps other reviewers from verifying, rejecting or claiming the server
+pub(crate) const CLAIM_EXPIRY_DAYS: i32 = 7;
+
+#[command(
+    help = "`!claim <server>` - Requires the reviewer role. Mark a pending server as being reviewed by you so no other admin reviews it at the same time."
+)]
+pub async fn claim<'a>(
+    _matrix_client: matrix_sdk::Client,
+    mut tx: mrsbfh::Sender,
+    config: Config<'a>,
+    sender: String,
+    args: Vec<&str>,
+) -> Result<(), Error>
+where
+    Config<'a>: mrsbfh::config::Loader + Clone,
+{
+    let server = match args.first() {
+        Some(server) => *server,
+        None => {
+            let content = AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(
+                "[ERROR] Missing server. Usage: `!claim <server>`",
+            ));
+            tx.send(content).await?;
+            return Ok(());
+        }
+    };
+
+    let database = get_database_pool(config.clone()).await?;
+
+    let claimed = sqlx::query!(
+        r#"
+            UPDATE servers
+            SET claimed_by = $2, claimed_at = now()
+            WHERE server_name = $1
+                AND verified = false
+                AND (claimed_by IS NULL OR claimed_by = $2 OR claimed_at < now() - make_interval(days => $3))
+            RETURNING server_name
+        "#,
+        server,
+        sender,
+        CLAIM_EXPIRY_DAYS
+    )
+    .fetch_optional(&database)
+    .await?;
+
+    let message = if claimed.is_some() {
+        format!(
+            "You are now reviewing {}. Finish with `!verify {}` or `!reject {} <reason>`.",
+            server, server, server
+        )
+    } else {
+        let current = sqlx::query!(
+            r#"SELECT claimed_by FROM servers WHERE server_name = $1 AND verified = false"#,
+            server
+        )
+        .fetch_optional(&database)
+        .await?;
+        match current.and_then(|x| x.claimed_by) {
+            Some(claimed_by) => format!(
+                "[ERROR] {} is already being reviewed by {}. They can release it with `!unclaim {}`, otherwise the claim expires after {} days.",
+                server, claimed_by, server, CLAIM_EXPIRY_DAYS
+            ),
+            None => format!(
+                "[ERROR] There is no server named '{}' waiting for verification.",
+                server
+            ),
+        }
+    };
+    let content = AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(message));
+    tx.send(content).await?;
+
+    Ok(())
+}
+
+/// Returns the reviewer who claimed the pending server if it is not `actor`. Only they may verify or reject it until the claim expires.
+pub(crate) fn claimed_by_other<'a>(server: &'a Server, actor: &str) -> Option<&'a str> {
+    if server.verified || is_expired(server) {
+        return None;
+    }
+    server.claimed_by.as_deref().filter(|x| *x != actor)
+}
+
+fn is_expired(server: &Server) -> bool {
+    server.claimed_at.map_or(false, |x| {
+        x < Utc::now() - Duration::days(CLAIM_EXPIRY_DAYS.into())
+    })
+}
+
+/// The error message for reviewers trying to verify or reject a server claimed by someone else
+pub(crate) fn claimed_error(server: &str, claimed_by: &str) -> String {
+    format!(
+        "[ERROR] {} is being reviewed by {}. Only they can verify or reject it until they release it with `!unclaim {}` or the claim expires after {} days.",
+        server, claimed_by, server, CLAIM_EXPIRY_DAYS
+    )
+}
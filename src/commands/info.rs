@@ -0,0 +1,64 @@
+use super::Permission;
+use crate::database::models::Role;
+use crate::{
+    config::Config, database::get_database_pool, database::servers, errors::Error, render,
+};
+use matrix_sdk::{
+    events::{room::message::MessageEventContent, AnyMessageEventContent},
+    identifiers::UserId,
+};
+use mrsbfh::commands::command;
+use std::convert::TryFrom;
+
+pub(super) const PERMISSION: Permission = Permission::Everyone;
+
+#[command(
+    help = "`!info <server>` - Show all details of a server. The keymaker team can also see pending servers and the review history."
+)]
+pub async fn info<'a>(
+    _matrix_client: matrix_sdk::Client,
+    mut tx: mrsbfh::Sender,
+    config: Config<'a>,
+    sender: String,
+    args: Vec<&str>,
+) -> Result<(), Error>
+where
+    Config<'a>: mrsbfh::config::Loader + Clone,
+{
+    let server_name = match args.first() {
+        Some(server_name) => *server_name,
+        None => {
+            let content = AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(
+                "[ERROR] Missing server. Usage: `!info <server>`",
+            ));
+            tx.send(content).await?;
+            return Ok(());
+        }
+    };
+    let sender_id_typed = UserId::try_from(sender).unwrap();
+    let database = get_database_pool(config.clone()).await?;
+    let is_team_member =
+        super::role_of(&config, &database, &sender_id_typed).await? >= Some(Role::ReadOnly);
+    let server = match servers::by_name(&database, server_name).await? {
+        Some(server) if server.verified || is_team_member => server,
+        _ => {
+            let content = AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(
+                format!("[ERROR] There is no listed server named '{}'.", server_name),
+            ));
+            tx.send(content).await?;
+            return Ok(());
+        }
+    };
+
+    let (plain, html) = if is_team_member {
+        let rejections = servers::rejections(&database, server_name).await?;
+        render::server_card(&server, Some(&rejections))
+    } else {
+        render::server_card(&server, None)
+    };
+    let content =
+        AnyMessageEventContent::RoomMessage(MessageEventContent::notice_html(plain, html));
+    tx.send(content).await?;
+
+    Ok(())
+}
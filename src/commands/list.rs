@@ -0,0 +1,48 @@
+use super::Permission;
+use crate::{
+    config::Config, database::get_database_pool, database::servers, errors::Error, render,
+};
+use matrix_sdk::events::{room::message::MessageEventContent, AnyMessageEventContent};
+use mrsbfh::commands::command;
+
+pub(super) const PERMISSION: Permission = Permission::Everyone;
+
+#[command(
+    help = "`!list [category] [page]` - List the servers of the keymaker project, optionally only the ones in a category."
+)]
+pub async fn list<'a>(
+    _matrix_client: matrix_sdk::Client,
+    mut tx: mrsbfh::Sender,
+    config: Config<'a>,
+    _sender: String,
+    mut args: Vec<&str>,
+) -> Result<(), Error>
+where
+    Config<'a>: mrsbfh::config::Loader + Clone,
+{
+    let page = match args.last().and_then(|x| x.parse::<usize>().ok()) {
+        Some(page) => {
+            args.pop();
+            page
+        }
+        None => 1,
+    };
+    let category = args.first().copied();
+
+    let database = get_database_pool(config.clone()).await?;
+    let servers = servers::verified(&database, category, None).await?;
+
+    let (title, next_page_command) = match category {
+        Some(category) => (
+            format!("Servers in category '{}'", category),
+            format!("!list {}", category),
+        ),
+        None => ("Servers".to_string(), "!list".to_string()),
+    };
+    let (plain, html) = render::server_list(&title, &servers, page, &next_page_command);
+    let content =
+        AnyMessageEventContent::RoomMessage(MessageEventContent::notice_html(plain, html));
+    tx.send(content).await?;
+
+    Ok(())
+}
@@ -1,10 +1,215 @@
 use crate::config::Config;
 use crate::errors::Error;
+use crate::extensions::AnyMessageEventContentExt;
+use matrix_sdk::{
+    events::{
+        custom::CustomEventContent,
+        room::message::{MessageEventContent, TextMessageEventContent},
+        AnyMessageEventContent, EventContent, SyncMessageEvent,
+    },
+    identifiers::RoomId,
+    Client,
+};
 use mrsbfh::commands::command_generate;
+use tokio::sync::mpsc;
+use tracing::*;
 
+mod authorization;
+mod cancel;
+mod check;
+mod claim;
+mod info;
+mod list;
+mod pending;
 mod register;
+mod reject;
+mod role;
+mod search;
+mod unclaim;
+mod verify;
+
+use authorization::Decision;
+pub(crate) use authorization::{role_of, Permission};
+pub(crate) use claim::{claimed_by_other, claimed_error, CLAIM_EXPIRY_DAYS};
+pub(crate) use reject::reject_server;
+pub(crate) use verify::verify_server;
 
 #[command_generate(bot_name = "Keymaker", description = "Control bot for keymaker")]
 enum Commands {
     Register,
+    Verify,
+    Reject,
+    Cancel,
+    Check,
+    List,
+    Search,
+    Info,
+    Pending,
+    Claim,
+    Unclaim,
+    Role,
+}
+
+impl Commands {
+    /// Looks up the command by the name used in messages
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "register" => Some(Commands::Register),
+            "verify" => Some(Commands::Verify),
+            "reject" => Some(Commands::Reject),
+            "cancel" => Some(Commands::Cancel),
+            "check" => Some(Commands::Check),
+            "list" => Some(Commands::List),
+            "search" => Some(Commands::Search),
+            "info" => Some(Commands::Info),
+            "pending" => Some(Commands::Pending),
+            "claim" => Some(Commands::Claim),
+            "unclaim" => Some(Commands::Unclaim),
+            "role" => Some(Commands::Role),
+            _ => None,
+        }
+    }
+
+    /// The permission declared by the command module
+    fn permission(&self) -> Permission {
+        match self {
+            Commands::Register => register::PERMISSION,
+            Commands::Verify => verify::PERMISSION,
+            Commands::Reject => reject::PERMISSION,
+            Commands::Cancel => cancel::PERMISSION,
+            Commands::Check => check::PERMISSION,
+            Commands::List => list::PERMISSION,
+            Commands::Search => search::PERMISSION,
+            Commands::Info => info::PERMISSION,
+            Commands::Pending => pending::PERMISSION,
+            Commands::Claim => claim::PERMISSION,
+            Commands::Unclaim => unclaim::PERMISSION,
+            Commands::Role => role::PERMISSION,
+        }
+    }
+}
+
+/// Looks up who may run the command. Unknown commands may not be run by anyone.
+fn permission(command: &str) -> Option<Permission> {
+    match command {
+        // Generated by mrsbfh and open to everyone
+        "help" => Some(Permission::Everyone),
+        _ => Commands::from_name(command).map(|x| x.permission()),
+    }
+}
+
+/// Marks responses which replace the first response of the command
+const EDIT_OF_FIRST_RESPONSE: &str = "dev.keymaker.edit_of_first_response";
+
+/// Turns the response into an edit of the first response the command sent, so commands can update a message without knowing its room.
+pub(crate) fn edit_first_response(content: AnyMessageEventContent) -> AnyMessageEventContent {
+    let event_type = content.event_type().to_string();
+    let mut json = serde_json::to_value(&content).expect("Unable to serialize message content");
+    json[EDIT_OF_FIRST_RESPONSE] = true.into();
+    AnyMessageEventContent::Custom(CustomEventContent { event_type, json })
+}
+
+/// Removes the mark added by `edit_first_response`. Returns whether it was there.
+fn take_edit_mark(content: &mut AnyMessageEventContent) -> bool {
+    if let AnyMessageEventContent::Custom(custom) = content {
+        if let Some(json) = custom.json.as_object_mut() {
+            return json.remove(EDIT_OF_FIRST_RESPONSE).is_some();
+        }
+    }
+    false
+}
+
+/// Runs the command in the message, if there is one, and sends its responses to the room.
+///
+/// Every response is a reply to the message so it is clear which command it belongs to.
+/// Responses made with `edit_first_response` replace the first response instead.
+pub(crate) fn dispatch(
+    client: Client,
+    config: Config<'static>,
+    room_id: RoomId,
+    event: SyncMessageEvent<MessageEventContent>,
+) {
+    let body = match event.content {
+        MessageEventContent::Text(TextMessageEventContent { ref body, .. }) => body.clone(),
+        _ => return,
+    };
+    if !body.starts_with('!') {
+        return;
+    }
+
+    tokio::spawn(handle(client, config, room_id, event, body));
+}
+
+/// Authorizes the sender, runs the command and forwards its responses
+#[instrument(skip(client, config, room_id, event, body), fields(room = %room_id))]
+async fn handle(
+    client: Client,
+    config: Config<'static>,
+    room_id: RoomId,
+    event: SyncMessageEvent<MessageEventContent>,
+    body: String,
+) {
+    let mut split = body.split_whitespace();
+    let command = split.next().unwrap_or_default().trim_start_matches('!');
+    let args: Vec<&str> = split.collect();
+    let sender = event.sender.to_string();
+    let (tx, mut rx) = mpsc::channel(100);
+
+    let permission = match permission(command) {
+        Some(permission) => permission,
+        None => {
+            debug!("Ignoring unknown command {} from {}", command, sender);
+            return;
+        }
+    };
+
+    // Privileged commands are refused in every room, not only in the admin room
+    match authorization::authorize(&config, &event.sender, permission).await {
+        Ok(Decision::Granted) => {}
+        Ok(Decision::Denied { required, actual }) => {
+            let message =
+                authorization::deny(&event.sender, &format!("`!{}`", command), required, actual);
+            let mut content =
+                AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(message));
+            content.add_reply_to(&room_id, &event);
+            if let Err(e) = client.room_send(&room_id, content, None).await {
+                error!("Unable to send response to {}: {}", command, e);
+            }
+            return;
+        }
+        Err(e) => {
+            error!("Unable to authorize {} for {}: {}", sender, command, e);
+            return;
+        }
+    }
+
+    let run = async {
+        if let Err(e) = match_command(command, client.clone(), config, tx, sender, args).await {
+            error!("Command {} failed: {}", command, e);
+        }
+    };
+    let forward = async {
+        let mut first_response = None;
+        while let Some(mut content) = rx.recv().await {
+            let content = if take_edit_mark(&mut content) {
+                match first_response {
+                    Some(ref event_id) => content.into_replacement(event_id),
+                    None => {
+                        error!("{} edited its first response before sending it", command);
+                        continue;
+                    }
+                }
+            } else {
+                content.add_reply_to(&room_id, &event);
+                content
+            };
+            match client.room_send(&room_id, content, None).await {
+                Ok(response) => {
+                    first_response.get_or_insert(response.event_id);
+                }
+                Err(e) => error!("Unable to send response to {}: {}", command, e),
+            }
+        }
+    };
+    tokio::join!(run, forward);
 }
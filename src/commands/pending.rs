@@ -0,0 +1,75 @@
+use super::Permission;
+use crate::database::models::Role;
+use crate::{
+    config::Config, database::get_database_pool, database::servers, errors::Error, render,
+};
+use matrix_sdk::events::{room::message::MessageEventContent, AnyMessageEventContent};
+use mrsbfh::commands::command;
+
+pub(super) const PERMISSION: Permission = Permission::Role(Role::ReadOnly);
+
+#[command(
+    help = "`!pending [page]` - Requires the read-only role. List all servers waiting for manual verification, oldest first."
+)]
+pub async fn pending<'a>(
+    _matrix_client: matrix_sdk::Client,
+    mut tx: mrsbfh::Sender,
+    config: Config<'a>,
+    _sender: String,
+    args: Vec<&str>,
+) -> Result<(), Error>
+where
+    Config<'a>: mrsbfh::config::Loader + Clone,
+{
+    let page = args
+        .first()
+        .and_then(|x| x.parse::<usize>().ok())
+        .unwrap_or(1);
+
+    let database = get_database_pool(config.clone()).await?;
+    let pending_servers = servers::pending(&database).await?;
+    if pending_servers.is_empty() {
+        let content = AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(
+            "No servers are waiting for verification.",
+        ));
+        tx.send(content).await?;
+        return Ok(());
+    }
+
+    let (shown, page, pages) = render::paginate(&pending_servers, page);
+    let mut plain = format!(
+        "{} servers are waiting for verification (page {}/{})\n",
+        pending_servers.len(),
+        page,
+        pages
+    );
+    let mut html = format!(
+        "<h3>{} servers are waiting for verification (page {}/{})</h3>\n",
+        pending_servers.len(),
+        page,
+        pages
+    );
+    for server in shown {
+        let (entry_plain, entry_html) = render::pending_entry(server);
+        plain.push('\n');
+        plain.push_str(&entry_plain);
+        html.push_str(&entry_html);
+    }
+    let footer = "Use `!claim <server>` before reviewing, then `!verify <server>` or `!reject <server> <reason>`. `!unclaim <server>` hands a server back to the queue.";
+    plain.push('\n');
+    plain.push_str(footer);
+    html.push_str(&format!("<p>{}</p>", render::escape_html(footer)));
+    if page < pages {
+        plain.push_str(&format!("\nUse `!pending {}` for the next page.", page + 1));
+        html.push_str(&format!(
+            "<p>Use <code>!pending {}</code> for the next page.</p>",
+            page + 1
+        ));
+    }
+
+    let content =
+        AnyMessageEventContent::RoomMessage(MessageEventContent::notice_html(plain, html));
+    tx.send(content).await?;
+
+    Ok(())
+}
@@ -1,20 +1,28 @@
+use super::Permission;
+use crate::database::{models::Server, servers};
+use crate::extensions::ClientExt;
 use crate::models::well_known::WellKnown;
+use crate::render;
+use crate::validation::{self, logo::Logo, Step, StepResult, StepStatus, Validation};
 use crate::{config::Config, database::get_database_pool};
 use crate::{errors::Error, models::well_known::ServerRegistrationStatus};
 use matrix_sdk::{
     events::{room::message::MessageEventContent, AnyMessageEventContent},
-    identifiers::{user_id::UserId, RoomId},
+    identifiers::user_id::UserId,
 };
 use mrsbfh::commands::command;
-use reqwest::{Client, StatusCode};
+use sqlx::postgres::PgPool;
 use std::convert::TryFrom;
+use std::io::Cursor;
+
+pub(super) const PERMISSION: Permission = Permission::Everyone;
 
 #[command(
     help = "`!register` - Register your server to the keymaker project. You need to be server admin for this. For further information checkout [PLACEHOLDER]"
 )]
 pub async fn register<'a>(
     matrix_client: matrix_sdk::Client,
-    mut tx: mrsbfh::Sender,
+    tx: mrsbfh::Sender,
     config: Config<'a>,
     sender: String,
     mut _args: Vec<&str>,
@@ -22,221 +30,331 @@ pub async fn register<'a>(
 where
     Config<'a>: mrsbfh::config::Loader + Clone,
 {
-    // Signal verification start
-    let content = AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(
-        "Starting verification process...",
-    ));
-    tx.send(content).await?;
-
     let sender_id_typed = UserId::try_from(sender.clone()).unwrap();
     let server = sender_id_typed.server_name().as_str();
 
-    // Signal step 1
-    let content = AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(
-        "[Step 1/8] Getting well-known file...",
-    ));
-    tx.send(content).await?;
+    let database = get_database_pool(config.clone()).await?;
 
-    let well_known_url =
-        "https://".to_string() + server + "/.well-known/matrix/mx.homeservers.metadata";
+    // Signal verification start
+    let mut progress = Progress::start(tx, server).await?;
 
-    let client = Client::new();
+    let mut validation = Validation::new(
+        validation::http_client(&config),
+        &config,
+        server,
+        Some(sender.as_str()),
+    );
 
     // TODO mention tutorial/fixes in errors
-    // TODO Add checkmark if step was fine
-    if let Ok(resp) = client.get(&well_known_url).send().await {
-        // Signal step 2
-        let content = AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(
-            "[Step 2/8] Ensuring .well-known file is reachable...",
-        ));
-        tx.send(content).await?;
+    while let Some(result) = validation.next_step().await {
+        let failed = matches!(result.status, StepStatus::Failed(_));
+        progress.steps.push(result.clone());
+        if failed {
+            progress
+                .update(Some(
+                    "[ERROR] Registration failed. Please fix the issue above and run `!register` again.",
+                ))
+                .await?;
+            return Ok(());
+        }
+        progress.update(None).await?;
+    }
+    let report = validation.into_report();
+    let check_report = report.checklist();
+    let well_known = report
+        .well_known
+        .expect("Validation passed without well-known");
 
-        // Check for status 200
-        if resp.status() != StatusCode::OK {
-            let content =
-                AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(format!(
-                    "[ERROR] .well-known file at: '{}' returned incorrect status code {}. We expect Status Code 200.",
-                    well_known_url,
-                    resp.status()
-                )));
-            tx.send(content).await?;
+    // The listing is keyed by the server_name of the well-known file, which may differ from the sender's server
+    let existing_server = servers::by_name(&database, &well_known.server_name).await?;
+    if let Some(ref existing_server) = existing_server {
+        if !existing_server.verified {
+            progress
+                .update(Some(&format!(
+                    "[ERROR] '{}' is already waiting for manual verification. The bot will notify you about any update. Use `!cancel` to withdraw the registration.",
+                    existing_server.server_name
+                )))
+                .await?;
             return Ok(());
         }
+    }
 
-        // Signal step 3
-        let content = AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(
-            "[Step 3/8] Ensuring .well-known file is valid...",
-        ));
-        tx.send(content).await?;
+    if let Some(existing_server) = existing_server {
+        let summary = update_listing(
+            matrix_client,
+            &config,
+            &database,
+            existing_server,
+            well_known,
+            report.logo,
+            check_report,
+        )
+        .await?;
+        progress.update(Some(&summary)).await?;
+        return Ok(());
+    }
 
-        // Verify Json
-        match resp.json::<WellKnown>().await {
-            Ok(well_known) => {
-                // Signal step 4
-                let content = AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(
-                    "[Step 4/8] Ensuring .well-known file has you listed as an admin of the server...",
-                ));
-                tx.send(content).await?;
-
-                // Ensure sender is an admin of the server
-                if !well_known.admins.iter().any(|x| x == &sender) {
-                    let content =
-                        AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(format!(
-                            "[ERROR] According to the .well-known file at: '{}' you are not any of the admins of this homeserver. Your mxid: {}, Admins in the .well-known config: {:?}",
-                            well_known_url,
-                            sender,
-                            well_known.admins
-                        )));
-                    tx.send(content).await?;
-                }
-
-                // Signal step 5
-                let content =
-                    AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(
-                        "[Step 5/8] Ensuring .well-known file server_name field is reachable...",
-                    ));
-                tx.send(content).await?;
-
-                // Ensure server_name is reachable
-                let server_name_address = format!("https://{}", well_known.server_name);
-                if client.head(&server_name_address).send().await.is_err() {
-                    let content =
-                        AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(format!(
-                            "[ERROR] The server_name field from the .well-known file ('{}') cannot be reached.",
-                            server_name_address,
-                        )));
-                    tx.send(content).await?;
-                    return Ok(());
-                }
-
-                // Signal step 6
-                let content =
-                    AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(
-                        "[Step 6/8] Ensuring .well-known file url field is reachable...",
-                    ));
-                tx.send(content).await?;
-
-                // Ensure url is reachable
-                let url_address = format!("https://{}", well_known.url);
-                if client.head(&url_address).send().await.is_err() {
-                    let content =
-                        AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(format!(
-                            "[ERROR] The url field from the .well-known file ('{}') cannot be reached.",
-                            url_address,
-                        )));
-                    tx.send(content).await?;
-                    return Ok(());
-                }
-
-                // Signal step 7
-                let content =
-                    AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(
-                        "[Step 7/8] Ensuring .well-known file logo_url field is reachable...",
-                    ));
-                tx.send(content).await?;
-
-                // Ensure logo_url is reachable
-                if let Some(ref logo_url) = well_known.logo_url {
-                    if client.head(logo_url).send().await.is_err() {
-                        let content =
-                            AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(format!(
-                                "[ERROR] The logo_url field from the .well-known file ('{}') cannot be reached.",
-                                logo_url,
-                            )));
-                        tx.send(content).await?;
-                        return Ok(());
-                    }
-                } else {
-                    let content =
-                        AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(
-                            "[Step 7/8] Skipping check as no logo_url was defined.",
-                        ));
-                    tx.send(content).await?;
-                }
-
-                let database = get_database_pool(config.clone()).await?;
-
-                sqlx::query!(
-                    r#"
-                        INSERT INTO servers ( name, url, server_name, logo_url, admins, categories, rules, description, registration_status, verified )
-                        VALUES ( $1, $2, $3, $4, $5, $6, $7, $8, $9, $10 )
-                    "#,
-                    well_known.name,
-                    well_known.url,
-                    well_known.server_name,
-                    well_known.logo_url,
-                    &well_known.admins,
-                    &well_known.categories,
-                    well_known.rules,
-                    well_known.description,
-                    well_known.registration_status as ServerRegistrationStatus,
-                    false
-                )
-                .execute(&database)
-                    .await?;
-
-                for category in well_known.categories {
-                    sqlx::query!(r#"INSERT INTO servers_categories (server_url, category_name) VALUES ( $1, $2 )"#,well_known.url,category).execute(&database).await?;
-                }
-
-                let content =
-                    AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(
-                        format!("@room New Server needs verification: {}", server),
-                    ));
-
-                if let Ok(ref room_id) = RoomId::try_from(config.admin_room_id.as_ref()) {
-                    if matrix_client
-                        .room_send(room_id, content, None)
-                        .await
-                        .is_ok()
-                    {
-                        let content =
-                            AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(
-                                "[Step 8/8] Server fulfilled automated tests. The server was sent to manual verification. This can take up to some days. The bot will notify you about any update.",
-                            ));
-                        tx.send(content).await?;
-                    } else {
-                        let content =
-                            AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(
-                                "[ERROR][Step 8/8] Server fulfilled automated tests. But the bot wasn't able to inform the project admins. Please try again another day or report this at #serverlist:nordgedanken.dev .",
-                            ));
-                        tx.send(content).await?;
-                    }
-                } else {
-                    let content =
-                        AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(
-                            "[ERROR][Step 8/8] Server fulfilled automated tests. But the bot wasn't able to inform the project admins. Please try again another day or report this at #serverlist:nordgedanken.dev .",
-                        ));
-                    tx.send(content).await?;
-                }
-
-                // TODO check if already ran to not rerun if being verified
-                // TODO add admin commands to press !verify which write verified == 1 to the db (causes it to show up) and tell user it successfully got added
-                // TODO add admin command !reject <reason> to reject a server (deletes it from database)
-                // TODO add user command !cancel which stops manual verification and deletes it from db
-                // TODO add admin command !dm which asks for a dm with the server admin
-            }
-            Err(e) => {
-                tracing::error!("Error parsing json: {:?}", e);
-                let content = AnyMessageEventContent::RoomMessage(
-                    MessageEventContent::notice_plain(format!(
-                        "[ERROR] .well-known file at: '{}' has invalid format.",
-                        well_known_url
-                    )),
-                );
-                tx.send(content).await?;
-                return Ok(());
-            }
-        }
+    let logo_mxc = mirror_logo(&matrix_client, &config, report.logo).await;
+
+    let mut transaction = database.begin().await?;
+    sqlx::query!(
+        r#"
+            INSERT INTO servers ( name, url, server_name, logo_url, admins, categories, rules, description, registration_status, verified, registered_by, logo_mxc, check_report )
+            VALUES ( $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13 )
+        "#,
+        well_known.name,
+        well_known.url,
+        well_known.server_name,
+        well_known.logo_url,
+        &well_known.admins,
+        &well_known.categories,
+        well_known.rules,
+        well_known.description,
+        well_known.registration_status as ServerRegistrationStatus,
+        false,
+        sender,
+        logo_mxc,
+        check_report
+    )
+    .execute(&mut transaction)
+    .await?;
+
+    for category in well_known.categories {
+        sqlx::query!(
+            r#"INSERT INTO servers_categories (server_url, category_name) VALUES ( $1, $2 )"#,
+            well_known.url,
+            category
+        )
+        .execute(&mut transaction)
+        .await?;
+    }
+    transaction.commit().await?;
+
+    let content = AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(format!(
+        "@room New Server needs verification: {}. React with ✅ to verify or ❌ to reject it.",
+        server
+    )));
+
+    if request_review(
+        &matrix_client,
+        &config,
+        &database,
+        &well_known.server_name,
+        content,
+    )
+    .await
+    {
+        progress
+            .update(Some(
+                "Server fulfilled automated tests. The server was sent to manual verification. This can take up to some days. The bot will notify you about any update.",
+            ))
+            .await?;
     } else {
+        progress
+            .update(Some(
+                "[ERROR] Server fulfilled automated tests. But the bot wasn't able to inform the project admins. Please try again another day or report this at #serverlist:nordgedanken.dev .",
+            ))
+            .await?;
+    }
+
+    // TODO add admin command !dm which asks for a dm with the server admin
+
+    Ok(())
+}
+
+/// The single message showing the registration progress. It is edited after every step.
+///
+/// It has to be the first response of the command as the edits refer to that.
+struct Progress {
+    tx: mrsbfh::Sender,
+    server: String,
+    steps: Vec<StepResult>,
+}
+
+impl Progress {
+    /// Sends the still empty checklist
+    async fn start(mut tx: mrsbfh::Sender, server: &str) -> Result<Self, Error> {
+        let (plain, html) = render::registration_progress(server, &[], Step::ALL.len(), None);
         let content =
-            AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(format!(
-                "[ERROR] Unable to find well_known file at: '{}'. This is most likely due to a connectivity issue.",
-                well_known_url
-            )));
+            AnyMessageEventContent::RoomMessage(MessageEventContent::notice_html(plain, html));
         tx.send(content).await?;
-        return Ok(());
+
+        Ok(Self {
+            tx,
+            server: server.to_string(),
+            steps: vec![],
+        })
     }
 
-    Ok(())
+    /// Replaces the message with the current checklist and the summary, if already known
+    async fn update(&mut self, summary: Option<&str>) -> Result<(), Error> {
+        let (plain, html) =
+            render::registration_progress(&self.server, &self.steps, Step::ALL.len(), summary);
+        let content =
+            AnyMessageEventContent::RoomMessage(MessageEventContent::notice_html(plain, html));
+        self.tx.send(super::edit_first_response(content)).await?;
+        Ok(())
+    }
+}
+
+/// Posts the review request to the admin room and remembers it so admins can react to it
+async fn request_review<'a>(
+    matrix_client: &matrix_sdk::Client,
+    config: &Config<'a>,
+    database: &PgPool,
+    server_name: &str,
+    content: AnyMessageEventContent,
+) -> bool {
+    let event_id = match matrix_client.send_to_admin_room(config, content).await {
+        Some(event_id) => event_id,
+        None => return false,
+    };
+    if let Err(e) = sqlx::query!(
+        r#"UPDATE servers SET review_event_id = $2 WHERE server_name = $1"#,
+        server_name,
+        event_id.to_string()
+    )
+    .execute(database)
+    .await
+    {
+        tracing::error!("Unable to store the review event: {}", e);
+    }
+    true
+}
+
+/// Uploads the logo to the media repository of the bot's homeserver if enabled in the config
+async fn mirror_logo<'a>(
+    matrix_client: &matrix_sdk::Client,
+    config: &Config<'a>,
+    logo: Option<Logo>,
+) -> Option<String> {
+    if !config.mirror_logos {
+        return None;
+    }
+    let logo = logo?;
+    match matrix_client
+        .upload(&logo.format.mime(), &mut Cursor::new(logo.bytes))
+        .await
+    {
+        Ok(response) => Some(response.content_uri.to_string()),
+        Err(e) => {
+            tracing::error!("Unable to upload logo: {}", e);
+            None
+        }
+    }
+}
+
+/// Updates an already verified listing with the content of the freshly fetched well-known file.
+///
+/// Returns the summary to show to the registering admin.
+async fn update_listing<'a>(
+    matrix_client: matrix_sdk::Client,
+    config: &Config<'a>,
+    database: &PgPool,
+    existing_server: Server,
+    well_known: WellKnown,
+    logo: Option<Logo>,
+    check_report: String,
+) -> Result<String, Error> {
+    let changed = existing_server.changed_fields(&well_known);
+    if changed.is_empty() {
+        return Ok(
+            "Server fulfilled automated tests. Your listing is already up to date.".to_string(),
+        );
+    }
+
+    let needs_review = changed.iter().any(|x| Server::SENSITIVE_FIELDS.contains(x));
+
+    let logo_mxc = if changed.contains(&"logo_url") || existing_server.logo_mxc.is_none() {
+        mirror_logo(&matrix_client, config, logo).await
+    } else {
+        existing_server.logo_mxc.clone()
+    };
+
+    let mut transaction = database.begin().await?;
+    sqlx::query!(
+        r#"
+            UPDATE servers
+            SET name = $2, url = $3, logo_url = $4, admins = $5, categories = $6, rules = $7,
+                description = $8, registration_status = $9, verified = $10, logo_mxc = $11,
+                check_report = $12,
+                claimed_by = CASE WHEN $10 THEN claimed_by END,
+                claimed_at = CASE WHEN $10 THEN claimed_at END,
+                verified_by = CASE WHEN $10 THEN verified_by END,
+                verified_at = CASE WHEN $10 THEN verified_at END,
+                updated_at = now()
+            WHERE server_name = $1
+        "#,
+        existing_server.server_name,
+        well_known.name,
+        well_known.url,
+        well_known.logo_url,
+        &well_known.admins,
+        &well_known.categories,
+        well_known.rules,
+        well_known.description,
+        well_known.registration_status as ServerRegistrationStatus,
+        !needs_review,
+        logo_mxc,
+        check_report
+    )
+    .execute(&mut transaction)
+    .await?;
+
+    for category in existing_server
+        .categories
+        .iter()
+        .filter(|x| !well_known.categories.contains(x))
+    {
+        sqlx::query!(
+            r#"DELETE FROM servers_categories WHERE server_url = $1 AND category_name = $2"#,
+            well_known.url,
+            category
+        )
+        .execute(&mut transaction)
+        .await?;
+    }
+    for category in well_known
+        .categories
+        .iter()
+        .filter(|x| !existing_server.categories.contains(x))
+    {
+        sqlx::query!(
+            r#"INSERT INTO servers_categories (server_url, category_name) VALUES ( $1, $2 )"#,
+            well_known.url,
+            category
+        )
+        .execute(&mut transaction)
+        .await?;
+    }
+    transaction.commit().await?;
+
+    if !needs_review {
+        return Ok(format!(
+            "Server fulfilled automated tests. Your listing was updated. Changed fields: {}",
+            changed.join(", ")
+        ));
+    }
+
+    let content = AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(format!(
+        "@room Changed Server needs verification: {} (changed fields: {}). React with ✅ to verify or ❌ to reject it.",
+        existing_server.server_name,
+        changed.join(", ")
+    )));
+    let informed_admins = request_review(
+        &matrix_client,
+        config,
+        database,
+        &existing_server.server_name,
+        content,
+    )
+    .await;
+    if informed_admins {
+        Ok(format!(
+            "Server fulfilled automated tests. The changed fields ({}) require a new manual verification, your server is unlisted until then. This can take up to some days. The bot will notify you about any update.",
+            changed.join(", ")
+        ))
+    } else {
+        Ok("[ERROR] Server fulfilled automated tests. But the bot wasn't able to inform the project admins. Please try again another day or report this at #serverlist:nordgedanken.dev .".to_string())
+    }
 }
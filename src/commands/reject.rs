@@ -0,0 +1,198 @@
+use super::Permission;
+use crate::database::models::Role;
+use crate::extensions::ClientExt;
+use crate::{config::Config, database::get_database_pool, database::servers, errors::Error};
+use matrix_sdk::{
+    events::{room::message::MessageEventContent, AnyMessageEventContent},
+    identifiers::UserId,
+};
+use mrsbfh::commands::command;
+use sqlx::postgres::PgPool;
+use std::convert::TryFrom;
+
+pub(super) const PERMISSION: Permission = Permission::Role(Role::Reviewer);
+
+#[command(
+    help = "`!reject [--delist] <server> <reason>` - Requires the reviewer role. Rejects a pending server, removes it from the database and sends the reason to its admins. Removing a listed server requires `--delist` and the moderator role."
+)]
+pub async fn reject<'a>(
+    matrix_client: matrix_sdk::Client,
+    mut tx: mrsbfh::Sender,
+    config: Config<'a>,
+    sender: String,
+    args: Vec<&str>,
+) -> Result<(), Error>
+where
+    Config<'a>: mrsbfh::config::Loader + Clone,
+{
+    let delist = args.first() == Some(&"--delist");
+    let args = if delist { &args[1..] } else { &args[..] };
+    if args.len() < 2 {
+        let content = AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(
+            "[ERROR] Missing server or reason. Usage: `!reject [--delist] <server> <reason>`",
+        ));
+        tx.send(content).await?;
+        return Ok(());
+    }
+    let server = args[0];
+    let reason = args[1..].join(" ");
+
+    let database = get_database_pool(config.clone()).await?;
+
+    // Reviewers handle the queue, taking down listed servers is up to moderators
+    if let Some(existing_server) = servers::by_name(&database, server).await? {
+        if existing_server.verified && !delist {
+            let content =
+                AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(format!(
+                    "[ERROR] '{}' is already listed. Use `!reject --delist {} <reason>` to remove it from the directory.",
+                    server, server
+                )));
+            tx.send(content).await?;
+            return Ok(());
+        }
+        if let Some(claimed_by) = super::claimed_by_other(&existing_server, &sender) {
+            let content = AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(
+                super::claimed_error(server, claimed_by),
+            ));
+            tx.send(content).await?;
+            return Ok(());
+        }
+        let sender_id_typed = UserId::try_from(sender.clone()).unwrap();
+        let role = super::role_of(&config, &database, &sender_id_typed).await?;
+        if existing_server.verified && role < Some(Role::Moderator) {
+            let message = super::authorization::deny(
+                &sender_id_typed,
+                &format!("Removing the listed server '{}'", server),
+                Role::Moderator,
+                role,
+            );
+            let content =
+                AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(message));
+            tx.send(content).await?;
+            return Ok(());
+        }
+    }
+
+    let unreachable_admins =
+        match reject_server(&matrix_client, &database, server, &reason, &sender, delist).await? {
+            Some(unreachable_admins) => unreachable_admins,
+            None => {
+                let content = AnyMessageEventContent::RoomMessage(
+                    MessageEventContent::notice_plain(format!(
+                        "[ERROR] There is no server named '{}' in the database.",
+                        server
+                    )),
+                );
+                tx.send(content).await?;
+                return Ok(());
+            }
+        };
+
+    let content = AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(format!(
+        "Server '{}' was rejected and removed from the database.",
+        server
+    )));
+    tx.send(content).await?;
+
+    if !unreachable_admins.is_empty() {
+        let content =
+            AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(format!(
+                "[ERROR] Unable to send the rejection reason to: {:?}",
+                unreachable_admins
+            )));
+        tx.send(content).await?;
+    }
+
+    Ok(())
+}
+
+/// Removes a server, records the rejection and sends the reason to the server admins.
+///
+/// Listed servers are only removed if `delist` is set, pending servers claimed by another admin never.
+/// Returns `None` if there is no such server, otherwise the server admins which could not be notified.
+pub(crate) async fn reject_server(
+    matrix_client: &matrix_sdk::Client,
+    database: &PgPool,
+    server: &str,
+    reason: &str,
+    admin: &str,
+    delist: bool,
+) -> Result<Option<Vec<String>>, Error> {
+    let mut transaction = database.begin().await?;
+
+    let rejected_server = sqlx::query!(
+        r#"
+            DELETE FROM servers
+            WHERE server_name = $1
+                AND (verified = false OR $2)
+                AND (verified = true OR claimed_by IS NULL OR claimed_by = $3 OR claimed_at < now() - make_interval(days => $4))
+            RETURNING url, admins
+        "#,
+        server,
+        delist,
+        admin,
+        super::CLAIM_EXPIRY_DAYS
+    )
+    .fetch_optional(&mut transaction)
+    .await?;
+
+    let rejected_server = match rejected_server {
+        Some(record) => record,
+        None => {
+            transaction.rollback().await?;
+            return Ok(None);
+        }
+    };
+
+    sqlx::query!(
+        r#"DELETE FROM servers_categories WHERE server_url = $1"#,
+        rejected_server.url
+    )
+    .execute(&mut transaction)
+    .await?;
+
+    sqlx::query!(
+        r#"
+            INSERT INTO rejections ( server_name, reason, rejected_by, rejected_at )
+            VALUES ( $1, $2, $3, now() )
+        "#,
+        server,
+        reason,
+        admin
+    )
+    .execute(&mut transaction)
+    .await?;
+
+    transaction.commit().await?;
+
+    // Let the server admins know what they need to fix
+    let mut unreachable_admins = vec![];
+    for server_admin in rejected_server.admins {
+        let notification =
+            AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(format!(
+                "Your server '{}' was rejected by the keymaker admins. Reason: {}\nPlease fix the issue and run `!register` again.",
+                server, reason
+            )));
+        let delivered = match UserId::try_from(server_admin.as_str()) {
+            Ok(ref user_id) => matrix_client
+                .send_direct_message(user_id, notification)
+                .await
+                .map_err(|e| tracing::error!("Unable to notify {}: {}", server_admin, e))
+                .is_ok(),
+            Err(e) => {
+                tracing::error!(
+                    "Invalid mxid '{}' stored for {}: {}",
+                    server_admin,
+                    server,
+                    e
+                );
+                false
+            }
+        };
+        if !delivered {
+            unreachable_admins.push(server_admin);
+        }
+    }
+
+    Ok(Some(unreachable_admins))
+}
@@ -0,0 +1,98 @@
+use super::Permission;
+use crate::database::{models::Role, roles};
+use crate::{config::Config, database::get_database_pool, errors::Error, render};
+use matrix_sdk::{
+    events::{room::message::MessageEventContent, AnyMessageEventContent},
+    identifiers::UserId,
+};
+use mrsbfh::commands::command;
+use std::convert::TryFrom;
+
+pub(super) const PERMISSION: Permission = Permission::Role(Role::Owner);
+
+#[command(
+    help = "`!role [list | grant <mxid> <role> | revoke <mxid>]` - Requires the owner role. Manage the roles of the keymaker team. Roles are read-only, reviewer, moderator and owner."
+)]
+pub async fn role<'a>(
+    _matrix_client: matrix_sdk::Client,
+    mut tx: mrsbfh::Sender,
+    config: Config<'a>,
+    sender: String,
+    args: Vec<&str>,
+) -> Result<(), Error>
+where
+    Config<'a>: mrsbfh::config::Loader + Clone,
+{
+    let database = get_database_pool(config.clone()).await?;
+
+    let content = match args.as_slice() {
+        [] | ["list"] => {
+            let grants = roles::all(&database).await?;
+            let mut plain = String::from("Roles of the keymaker team:\n");
+            let mut html = String::from("<h4>Roles of the keymaker team</h4>\n<ul>\n");
+            for admin in config.admins.iter() {
+                plain.push_str(&format!("{}: owner (config.yml)\n", admin));
+                html.push_str(&format!(
+                    "<li>{}: owner (config.yml)</li>\n",
+                    render::user_pill(admin)
+                ));
+            }
+            for grant in grants {
+                plain.push_str(&format!(
+                    "{}: {} (granted by {} on {})\n",
+                    grant.mxid,
+                    grant.role.name(),
+                    grant.granted_by,
+                    grant.granted_at.format("%Y-%m-%d")
+                ));
+                html.push_str(&format!(
+                    "<li>{}: {} (granted by {} on {})</li>\n",
+                    render::user_pill(&grant.mxid),
+                    grant.role.name(),
+                    render::user_pill(&grant.granted_by),
+                    grant.granted_at.format("%Y-%m-%d")
+                ));
+            }
+            html.push_str("</ul>\n");
+            AnyMessageEventContent::RoomMessage(MessageEventContent::notice_html(plain, html))
+        }
+        ["grant", mxid, role] => {
+            let message = match (UserId::try_from(*mxid), role.parse::<Role>()) {
+                (Err(_), _) => format!("[ERROR] '{}' is not a valid mxid.", mxid),
+                (_, Err(e)) => format!("[ERROR] {}", e),
+                (Ok(user_id), Ok(_)) if config.is_admin(&user_id) => format!(
+                    "[ERROR] {} is an owner listed in config.yml. Remove them there instead.",
+                    user_id
+                ),
+                (Ok(user_id), Ok(role)) => {
+                    roles::grant(&database, user_id.as_str(), role, &sender).await?;
+                    format!("{} now has the {} role.", user_id, role.name())
+                }
+            };
+            AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(message))
+        }
+        ["revoke", mxid] => {
+            let message = match UserId::try_from(*mxid) {
+                Err(_) => format!("[ERROR] '{}' is not a valid mxid.", mxid),
+                Ok(user_id) if config.is_admin(&user_id) => format!(
+                    "[ERROR] {} is an owner listed in config.yml. Remove them there instead.",
+                    user_id
+                ),
+                Ok(user_id) => {
+                    if roles::revoke(&database, user_id.as_str()).await? {
+                        format!("{} no longer has a role.", user_id)
+                    } else {
+                        format!("[ERROR] {} has no role.", user_id)
+                    }
+                }
+            };
+            AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(message))
+        }
+        _ => AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(
+            "[ERROR] Usage: `!role [list | grant <mxid> <role> | revoke <mxid>]`",
+        )),
+    };
+    tx.send(content).await?;
+
+    Ok(())
+}
@@ -0,0 +1,53 @@
+use super::Permission;
+use crate::{
+    config::Config, database::get_database_pool, database::servers, errors::Error, render,
+};
+use matrix_sdk::events::{room::message::MessageEventContent, AnyMessageEventContent};
+use mrsbfh::commands::command;
+
+pub(super) const PERMISSION: Permission = Permission::Everyone;
+
+#[command(
+    help = "`!search <query> [page]` - Search the servers of the keymaker project by name, description and categories."
+)]
+pub async fn search<'a>(
+    _matrix_client: matrix_sdk::Client,
+    mut tx: mrsbfh::Sender,
+    config: Config<'a>,
+    _sender: String,
+    mut args: Vec<&str>,
+) -> Result<(), Error>
+where
+    Config<'a>: mrsbfh::config::Loader + Clone,
+{
+    let page = match args.last().and_then(|x| x.parse::<usize>().ok()) {
+        Some(page) if args.len() > 1 => {
+            args.pop();
+            page
+        }
+        _ => 1,
+    };
+    if args.is_empty() {
+        let content = AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(
+            "[ERROR] Missing query. Usage: `!search <query> [page]`",
+        ));
+        tx.send(content).await?;
+        return Ok(());
+    }
+    let query = args.join(" ");
+
+    let database = get_database_pool(config.clone()).await?;
+    let servers = servers::search(&database, &query).await?;
+
+    let (plain, html) = render::server_list(
+        &format!("Servers matching '{}'", query),
+        &servers,
+        page,
+        &format!("!search {}", query),
+    );
+    let content =
+        AnyMessageEventContent::RoomMessage(MessageEventContent::notice_html(plain, html));
+    tx.send(content).await?;
+
+    Ok(())
+}
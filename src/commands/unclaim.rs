@@ -0,0 +1,109 @@
+use super::Permission;
+use crate::database::{models::Role, servers};
+use crate::{config::Config, database::get_database_pool, errors::Error};
+use matrix_sdk::{
+    events::{room::message::MessageEventContent, AnyMessageEventContent},
+    identifiers::UserId,
+};
+use mrsbfh::commands::command;
+use std::convert::TryFrom;
+
+pub(super) const PERMISSION: Permission = Permission::Role(Role::Reviewer);
+
+#[command(
+    help = "`!unclaim <server>` - Requires the reviewer role. Release your claim on a pending server so another admin can review it. Owners can release any claim."
+)]
+pub async fn unclaim<'a>(
+    _matrix_client: matrix_sdk::Client,
+    mut tx: mrsbfh::Sender,
+    config: Config<'a>,
+    sender: String,
+    args: Vec<&str>,
+) -> Result<(), Error>
+where
+    Config<'a>: mrsbfh::config::Loader + Clone,
+{
+    let server = match args.first() {
+        Some(server) => *server,
+        None => {
+            let content = AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(
+                "[ERROR] Missing server. Usage: `!unclaim <server>`",
+            ));
+            tx.send(content).await?;
+            return Ok(());
+        }
+    };
+
+    let database = get_database_pool(config.clone()).await?;
+
+    let claimed_by = match servers::by_name(&database, server).await? {
+        Some(existing_server) if !existing_server.verified => existing_server.claimed_by,
+        _ => {
+            let content =
+                AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(format!(
+                    "[ERROR] There is no server named '{}' waiting for verification.",
+                    server
+                )));
+            tx.send(content).await?;
+            return Ok(());
+        }
+    };
+    let claimed_by = match claimed_by {
+        Some(claimed_by) => claimed_by,
+        None => {
+            let content = AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(
+                format!("[ERROR] Nobody claimed {}.", server),
+            ));
+            tx.send(content).await?;
+            return Ok(());
+        }
+    };
+
+    // Reviewers release their own claims, owners step in for reviewers who became inactive
+    if claimed_by != sender {
+        let sender_id_typed = UserId::try_from(sender.clone()).unwrap();
+        let role = super::role_of(&config, &database, &sender_id_typed).await?;
+        if role < Some(Role::Owner) {
+            let message = super::authorization::deny(
+                &sender_id_typed,
+                &format!("Releasing the claim of {} on '{}'", claimed_by, server),
+                Role::Owner,
+                role,
+            );
+            let content =
+                AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(message));
+            tx.send(content).await?;
+            return Ok(());
+        }
+    }
+
+    // The claim is only released if nobody claimed the server in the meantime
+    let released = sqlx::query!(
+        r#"
+            UPDATE servers
+            SET claimed_by = NULL, claimed_at = NULL
+            WHERE server_name = $1 AND verified = false AND claimed_by = $2
+            RETURNING server_name
+        "#,
+        server,
+        claimed_by
+    )
+    .fetch_optional(&database)
+    .await?;
+
+    let message = if released.is_some() {
+        format!(
+            "Released the claim of {} on {}. Any reviewer can claim it now.",
+            claimed_by, server
+        )
+    } else {
+        format!(
+            "[ERROR] The claim on {} changed in the meantime. Check `!pending` and try again.",
+            server
+        )
+    };
+    let content = AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(message));
+    tx.send(content).await?;
+
+    Ok(())
+}
@@ -0,0 +1,142 @@
+use super::Permission;
+use crate::database::models::Role;
+use crate::extensions::ClientExt;
+use crate::{config::Config, database::get_database_pool, database::servers, errors::Error};
+use matrix_sdk::{
+    events::{room::message::MessageEventContent, AnyMessageEventContent},
+    identifiers::UserId,
+};
+use mrsbfh::commands::command;
+use sqlx::postgres::PgPool;
+use std::convert::TryFrom;
+
+pub(super) const PERMISSION: Permission = Permission::Role(Role::Reviewer);
+
+#[command(
+    help = "`!verify <server>` - Requires the reviewer role. Publishes a server which is pending manual verification and notifies its admin."
+)]
+pub async fn verify<'a>(
+    matrix_client: matrix_sdk::Client,
+    mut tx: mrsbfh::Sender,
+    config: Config<'a>,
+    sender: String,
+    args: Vec<&str>,
+) -> Result<(), Error>
+where
+    Config<'a>: mrsbfh::config::Loader + Clone,
+{
+    let server = match args.first() {
+        Some(server) => *server,
+        None => {
+            let content = AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(
+                "[ERROR] Missing server. Usage: `!verify <server>`",
+            ));
+            tx.send(content).await?;
+            return Ok(());
+        }
+    };
+
+    let database = get_database_pool(config.clone()).await?;
+
+    if let Some(existing_server) = servers::by_name(&database, server).await? {
+        if let Some(claimed_by) = super::claimed_by_other(&existing_server, &sender) {
+            let content = AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(
+                super::claimed_error(server, claimed_by),
+            ));
+            tx.send(content).await?;
+            return Ok(());
+        }
+    }
+
+    let verification = match verify_server(&matrix_client, &database, server, &sender).await? {
+        Some(verification) => verification,
+        None => {
+            let content =
+                AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(format!(
+                    "[ERROR] There is no server named '{}' waiting for verification.",
+                    server
+                )));
+            tx.send(content).await?;
+            return Ok(());
+        }
+    };
+
+    let content = AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(format!(
+        "Server '{}' was verified and is now listed.",
+        server
+    )));
+    tx.send(content).await?;
+
+    if !verification.notified {
+        let content =
+            AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(format!(
+                "[ERROR] Unable to notify {} about the verification.",
+                verification.registered_by
+            )));
+        tx.send(content).await?;
+    }
+
+    Ok(())
+}
+
+pub(crate) struct Verification {
+    /// Admin who registered the server
+    pub registered_by: String,
+    /// Whether `registered_by` could be told about the verification
+    pub notified: bool,
+}
+
+/// Publishes a pending server and notifies the admin who registered it.
+///
+/// Returns `None` if no server with that name is waiting for verification or another admin claimed it.
+pub(crate) async fn verify_server(
+    matrix_client: &matrix_sdk::Client,
+    database: &PgPool,
+    server: &str,
+    admin: &str,
+) -> Result<Option<Verification>, Error> {
+    let verified_server = sqlx::query!(
+        r#"
+            UPDATE servers
+            SET verified = true, verified_by = $2, verified_at = now()
+            WHERE server_name = $1
+                AND verified = false
+                AND (claimed_by IS NULL OR claimed_by = $2 OR claimed_at < now() - make_interval(days => $3))
+            RETURNING registered_by
+        "#,
+        server,
+        admin,
+        super::CLAIM_EXPIRY_DAYS
+    )
+    .fetch_optional(database)
+    .await?;
+
+    let registered_by = match verified_server {
+        Some(record) => record.registered_by,
+        None => return Ok(None),
+    };
+
+    // Tell the admin who registered the server about the result
+    let notification = AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(
+        format!(
+            "Your server '{}' passed the manual verification and is now listed. Thank you for registering it!",
+            server
+        ),
+    ));
+    let notified = match UserId::try_from(registered_by.as_str()) {
+        Ok(ref user_id) => matrix_client
+            .send_direct_message(user_id, notification)
+            .await
+            .map_err(|e| tracing::error!("Unable to notify {}: {}", registered_by, e))
+            .is_ok(),
+        Err(e) => {
+            tracing::error!("Invalid mxid '{}' stored for {}: {}", registered_by, server, e);
+            false
+        }
+    };
+
+    Ok(Some(Verification {
+        registered_by,
+        notified,
+    }))
+}
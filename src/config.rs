@@ -1,6 +1,20 @@
+use crate::errors::Error;
+use matrix_sdk::identifiers::{RoomId, RoomIdOrAliasId, UserId};
 use mrsbfh::config::ConfigDerive;
 use serde::{Deserialize, Serialize};
+use serde_yaml::{Mapping, Value};
+use sqlx::{postgres::PgConnection, Connection};
 use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::net::{AddrParseError, SocketAddr};
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::{env, fmt, fs, io};
+use tracing::*;
+use url::Url;
+
+/// The config shared between the bot's tasks. It is replaced when `config.yml` is reloaded.
+pub type SharedConfig = Arc<RwLock<Config<'static>>>;
 
 #[derive(Debug, PartialEq, Serialize, Deserialize, Clone, ConfigDerive)]
 pub struct Config<'a> {
@@ -9,7 +23,455 @@ pub struct Config<'a> {
     pub password: Cow<'a, str>,
     pub store_path: Cow<'a, str>,
     pub admins: Vec<Cow<'a, str>>,
+    /// Room id or alias of the admin room
     pub admin_room_id: Cow<'a, str>,
+    /// The room id `admin_room_id` resolved to when the bot joined the admin room
+    #[serde(skip)]
+    pub admin_room: Option<RoomId>,
     pub session_path: Cow<'a, str>,
     pub database_url: Cow<'a, str>,
+    /// Seconds between two health checks of all listed servers
+    #[serde(default = "default_health_check_interval")]
+    pub health_check_interval: u64,
+    /// Number of failed health checks in a row until a server is flagged as unhealthy
+    #[serde(default = "default_health_check_max_failures")]
+    pub health_check_max_failures: i32,
+    /// Seconds until a request to a checked server times out
+    #[serde(default = "default_check_timeout")]
+    pub check_timeout: u64,
+    /// Categories servers are allowed to list themselves in
+    #[serde(default = "default_categories")]
+    pub categories: Vec<Cow<'a, str>>,
+    /// Whether to start a registration on checked servers to compare it with their registration_status.
+    /// Off by default as the periodic health check would start a registration on every listed server.
+    #[serde(default)]
+    pub probe_registration: bool,
+    /// Whether to upload the logos of registered servers to the media repository of the bot's homeserver
+    #[serde(default)]
+    pub mirror_logos: bool,
+    /// Address the read-only JSON API listens on, e.g. `127.0.0.1:8080`. The API is disabled if unset.
+    #[serde(default)]
+    pub api_listen_address: Option<Cow<'a, str>>,
+    /// Directory the `export` subcommand writes to unless another one is passed
+    #[serde(default = "default_export_path")]
+    pub export_path: Cow<'a, str>,
+    /// Tera template rendered to `index.html` by the `export` subcommand. No HTML is exported if unset.
+    #[serde(default)]
+    pub export_template: Option<Cow<'a, str>>,
+}
+
+fn default_health_check_interval() -> u64 {
+    6 * 60 * 60
+}
+
+fn default_health_check_max_failures() -> i32 {
+    3
+}
+
+fn default_check_timeout() -> u64 {
+    30
+}
+
+fn default_export_path<'a>() -> Cow<'a, str> {
+    Cow::Borrowed("export")
+}
+
+fn default_categories<'a>() -> Vec<Cow<'a, str>> {
+    [
+        "general",
+        "tech",
+        "gaming",
+        "art",
+        "science",
+        "regional",
+        "privacy",
+        "community",
+    ]
+    .iter()
+    .map(|x| Cow::Borrowed(*x))
+    .collect()
+}
+
+impl<'a> Config<'a> {
+    /// Checks if the user is listed as one of the bot admins. They always have the owner role.
+    pub fn is_admin(&self, user_id: &UserId) -> bool {
+        self.admins
+            .iter()
+            .filter_map(|x| UserId::try_from(x.as_ref()).ok())
+            .any(|x| &x == user_id)
+    }
+
+    /// The address the JSON API listens on, `None` if the API is disabled
+    pub fn api_listen_address(&self) -> Result<Option<SocketAddr>, AddrParseError> {
+        self.api_listen_address
+            .as_ref()
+            .map(|x| x.parse())
+            .transpose()
+    }
+}
+
+/// Where the value of a config field came from
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    File,
+    SecretFile(String),
+    Env(String),
+    Default,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::File => write!(f, "config file"),
+            Source::SecretFile(path) => write!(f, "secret file {}", path),
+            Source::Env(variable) => write!(f, "environment variable {}", variable),
+            Source::Default => write!(f, "default"),
+        }
+    }
+}
+
+/// How the value of an environment variable is read
+#[derive(Debug, Clone, Copy)]
+enum Kind {
+    /// Taken as is
+    Text,
+    /// Comma separated
+    List,
+    /// Parsed as YAML, for numbers and booleans
+    Scalar,
+}
+
+/// Every field of `Config` which can be overridden
+const FIELDS: &[(&str, Kind)] = &[
+    ("homeserver_url", Kind::Text),
+    ("mxid", Kind::Text),
+    ("password", Kind::Text),
+    ("store_path", Kind::Text),
+    ("admins", Kind::List),
+    ("admin_room_id", Kind::Text),
+    ("session_path", Kind::Text),
+    ("database_url", Kind::Text),
+    ("health_check_interval", Kind::Scalar),
+    ("health_check_max_failures", Kind::Scalar),
+    ("check_timeout", Kind::Scalar),
+    ("categories", Kind::List),
+    ("probe_registration", Kind::Scalar),
+    ("mirror_logos", Kind::Scalar),
+    ("api_listen_address", Kind::Text),
+    ("export_path", Kind::Text),
+    ("export_template", Kind::Text),
+];
+
+/// Fields which can be read from the file named by `<field>_file` and are never logged
+pub const SECRET_FIELDS: &[&str] = &["password", "database_url"];
+
+/// Loads the config file and applies the overrides. The file may be missing if everything is set in the environment.
+///
+/// For every field the last of these wins: the file, `<field>_file` in the file, `KEYMAKER_<FIELD>_FILE` and `KEYMAKER_<FIELD>`.
+/// Returns the config together with the source of every field.
+pub fn load(path: &str) -> Result<(Config<'static>, Vec<(&'static str, Source)>), Error> {
+    let mut values = match fs::read_to_string(path) {
+        Ok(text) if text.trim().is_empty() => Mapping::new(),
+        Ok(text) => serde_yaml::from_str(&text)?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Mapping::new(),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut sources = vec![];
+    for (field, kind) in FIELDS {
+        let key = Value::String(field.to_string());
+        let mut source = if values.contains_key(&key) {
+            Source::File
+        } else {
+            Source::Default
+        };
+
+        if SECRET_FIELDS.contains(field) {
+            let file_field = format!("{}_file", field);
+            let from_file = values
+                .remove(&Value::String(file_field.clone()))
+                .and_then(|x| x.as_str().map(str::to_string));
+            if let Some(secret_path) = env::var(env_variable(&file_field)).ok().or(from_file) {
+                let secret =
+                    fs::read_to_string(&secret_path).map_err(|source| Error::SecretFileError {
+                        field: *field,
+                        path: secret_path.clone(),
+                        source,
+                    })?;
+                values.insert(key.clone(), Value::String(secret.trim_end().to_string()));
+                source = Source::SecretFile(secret_path);
+            }
+        }
+
+        let variable = env_variable(field);
+        if let Ok(value) = env::var(&variable) {
+            let value = match kind {
+                Kind::Text => Value::String(value),
+                Kind::List => Value::Sequence(
+                    value
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|x| !x.is_empty())
+                        .map(|x| Value::String(x.to_string()))
+                        .collect(),
+                ),
+                Kind::Scalar => {
+                    serde_yaml::from_str(&value).map_err(|source| Error::EnvOverrideError {
+                        variable: variable.clone(),
+                        source,
+                    })?
+                }
+            };
+            values.insert(key, value);
+            source = Source::Env(variable);
+        }
+
+        sources.push((*field, source));
+    }
+
+    let config = serde_yaml::from_value(Value::Mapping(values))?;
+    Ok((config, sources))
+}
+
+fn env_variable(field: &str) -> String {
+    format!("KEYMAKER_{}", field.to_uppercase())
+}
+
+/// Logs the value and the source of every field. Secrets are redacted.
+pub fn log_summary(config: &Config<'_>, sources: &[(&str, Source)]) {
+    let values = serde_json::to_value(config).expect("Unable to serialize config");
+    for (field, source) in sources {
+        let value = if SECRET_FIELDS.contains(field) {
+            "<redacted>".to_string()
+        } else {
+            values[*field].to_string()
+        };
+        info!("{} = {} (from {})", field, value, source);
+    }
+}
+
+/// Checks the syntax of every field which is parsed later on
+pub fn syntax_problems(config: &Config<'_>) -> Vec<Error> {
+    let mut problems = vec![];
+
+    if let Err(source) = Url::parse(&config.homeserver_url) {
+        problems.push(Error::InvalidUrl {
+            field: "homeserver_url",
+            value: config.homeserver_url.to_string(),
+            source,
+        });
+    }
+    if let Err(source) = UserId::try_from(config.mxid.as_ref()) {
+        problems.push(Error::InvalidMxid {
+            field: "mxid",
+            value: config.mxid.to_string(),
+            source,
+        });
+    }
+    for admin in config.admins.iter() {
+        if let Err(source) = UserId::try_from(admin.as_ref()) {
+            problems.push(Error::InvalidMxid {
+                field: "admins",
+                value: admin.to_string(),
+                source,
+            });
+        }
+    }
+    if let Err(source) = RoomIdOrAliasId::try_from(config.admin_room_id.as_ref()) {
+        problems.push(Error::InvalidRoomId {
+            field: "admin_room_id",
+            value: config.admin_room_id.to_string(),
+            source,
+        });
+    }
+    if let Err(source) = config.api_listen_address() {
+        problems.push(Error::InvalidAddress {
+            field: "api_listen_address",
+            value: config
+                .api_listen_address
+                .as_deref()
+                .unwrap_or_default()
+                .to_string(),
+            source,
+        });
+    }
+    if config.health_check_interval == 0 {
+        problems.push(Error::InvalidValue {
+            field: "health_check_interval",
+            message: "must be greater than 0",
+        });
+    }
+    if config.check_timeout == 0 {
+        problems.push(Error::InvalidValue {
+            field: "check_timeout",
+            message: "must be greater than 0",
+        });
+    }
+    if config.categories.is_empty() {
+        problems.push(Error::InvalidValue {
+            field: "categories",
+            message: "must not be empty",
+        });
+    }
+
+    problems
+}
+
+/// Checks everything the bot needs to start: the syntax of all fields, the database and, if `check_paths` is set, the paths the bot writes to.
+///
+/// The paths are only needed for logging in, so they are not checked for the subcommands.
+/// All problems are reported at once.
+pub async fn validate(config: &Config<'_>, check_paths: bool) -> Result<(), Error> {
+    let mut problems = syntax_problems(config);
+
+    if check_paths {
+        if let Err(source) = check_directory_writable(Path::new(config.store_path.as_ref())) {
+            problems.push(Error::PathNotWritable {
+                field: "store_path",
+                path: config.store_path.to_string(),
+                source,
+            });
+        }
+        if let Err(source) = check_file_writable(Path::new(config.session_path.as_ref())) {
+            problems.push(Error::PathNotWritable {
+                field: "session_path",
+                path: config.session_path.to_string(),
+                source,
+            });
+        }
+    }
+    match PgConnection::connect(&config.database_url).await {
+        Ok(connection) => {
+            if let Err(e) = connection.close().await {
+                warn!("Unable to close the database connection: {}", e);
+            }
+        }
+        Err(source) => problems.push(Error::DatabaseUnreachable { source }),
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::InvalidConfig(problems))
+    }
+}
+
+/// Creates the directory if needed and makes sure files can be created in it
+fn check_directory_writable(path: &Path) -> io::Result<()> {
+    fs::create_dir_all(path)?;
+    let probe = path.join(".keymaker-write-test");
+    fs::File::create(&probe)?;
+    fs::remove_file(&probe)
+}
+
+/// Makes sure the file can be written without touching its content
+fn check_file_writable(path: &Path) -> io::Result<()> {
+    if path.exists() {
+        fs::OpenOptions::new().append(true).open(path)?;
+        return Ok(());
+    }
+    fs::File::create(path)?;
+    fs::remove_file(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_temp(name: &str, content: &str) -> String {
+        let path = env::temp_dir().join(format!("keymaker-{}-{}", std::process::id(), name));
+        fs::write(&path, content).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    fn source_of<'a>(sources: &'a [(&'static str, Source)], field: &str) -> &'a Source {
+        &sources.iter().find(|(x, _)| *x == field).unwrap().1
+    }
+
+    // The environment is shared by all tests, so every override is checked in this one test
+    #[test]
+    fn later_sources_override_earlier_ones() {
+        let file_secret = write_temp("file-secret", "from secret file\n");
+        let env_secret = write_temp("env-secret", "from env secret file\n");
+        let path = write_temp(
+            "config.yml",
+            &format!(
+                r#"
+homeserver_url: "https://example.org"
+mxid: "@bot:example.org"
+password: "from file"
+password_file: "{}"
+store_path: "store"
+session_path: "session"
+admin_room_id: "!admin:example.org"
+database_url: "from file"
+database_url_file: "{}"
+admins:
+  - "@admin:example.org"
+check_timeout: 10
+"#,
+                file_secret, file_secret
+            ),
+        );
+        env::set_var("KEYMAKER_DATABASE_URL_FILE", &env_secret);
+        env::set_var("KEYMAKER_MXID", "@other:example.org");
+        env::set_var("KEYMAKER_ADMINS", "@a:example.org, ,@b:example.org");
+        env::set_var("KEYMAKER_PROBE_REGISTRATION", "true");
+
+        let (config, sources) = load(&path).unwrap();
+        assert_eq!(config.homeserver_url, "https://example.org");
+        assert_eq!(source_of(&sources, "homeserver_url"), &Source::File);
+        assert_eq!(config.password, "from secret file");
+        assert_eq!(
+            source_of(&sources, "password"),
+            &Source::SecretFile(file_secret.clone())
+        );
+        assert_eq!(config.database_url, "from env secret file");
+        assert_eq!(
+            source_of(&sources, "database_url"),
+            &Source::SecretFile(env_secret.clone())
+        );
+        assert_eq!(config.mxid, "@other:example.org");
+        assert_eq!(
+            source_of(&sources, "mxid"),
+            &Source::Env("KEYMAKER_MXID".to_string())
+        );
+        assert_eq!(config.admins, vec!["@a:example.org", "@b:example.org"]);
+        assert!(config.probe_registration);
+        assert_eq!(config.check_timeout, 10);
+        assert_eq!(
+            config.health_check_interval,
+            default_health_check_interval()
+        );
+        assert_eq!(
+            source_of(&sources, "health_check_interval"),
+            &Source::Default
+        );
+
+        // The variable wins over the secret files
+        env::set_var("KEYMAKER_DATABASE_URL", "from env");
+        let (config, sources) = load(&path).unwrap();
+        assert_eq!(config.database_url, "from env");
+        assert_eq!(
+            source_of(&sources, "database_url"),
+            &Source::Env("KEYMAKER_DATABASE_URL".to_string())
+        );
+
+        env::set_var("KEYMAKER_CHECK_TIMEOUT", "[");
+        assert!(matches!(load(&path), Err(Error::EnvOverrideError { .. })));
+
+        for variable in &[
+            "KEYMAKER_DATABASE_URL_FILE",
+            "KEYMAKER_DATABASE_URL",
+            "KEYMAKER_MXID",
+            "KEYMAKER_ADMINS",
+            "KEYMAKER_PROBE_REGISTRATION",
+            "KEYMAKER_CHECK_TIMEOUT",
+        ] {
+            env::remove_var(variable);
+        }
+        for path in &[file_secret, env_secret, path] {
+            fs::remove_file(path).unwrap();
+        }
+    }
 }
@@ -1,9 +1,45 @@
 use crate::config::Config;
 use crate::errors::Error;
+use sqlx::migrate::Migrator;
 use sqlx::postgres::PgPool;
 use tracing::*;
 
 pub mod models;
+pub mod roles;
+pub mod servers;
+
+static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+/// Applies all embedded migrations.
+///
+/// Refuses to touch the database if it was already migrated by a newer version of the bot.
+#[instrument(skip(pool))]
+async fn run_migrations(pool: &PgPool) -> Result<(), Error> {
+    let binary_version = MIGRATOR.iter().map(|x| x.version).max().unwrap_or(0);
+
+    let (migrations_table_exists,): (bool,) =
+        sqlx::query_as("SELECT to_regclass('_sqlx_migrations') IS NOT NULL")
+            .fetch_one(pool)
+            .await?;
+    if migrations_table_exists {
+        let (database_version,): (Option<i64>,) =
+            sqlx::query_as("SELECT MAX(version) FROM _sqlx_migrations")
+                .fetch_one(pool)
+                .await?;
+        if let Some(database_version) = database_version {
+            if database_version > binary_version {
+                return Err(Error::SchemaTooNew {
+                    database_version,
+                    binary_version,
+                });
+            }
+        }
+    }
+
+    MIGRATOR.run(pool).await?;
+    info!("Database schema is at version {}", binary_version);
+    Ok(())
+}
 
 #[cfg(test)]
 #[instrument(skip(config))]
@@ -13,7 +49,9 @@ where
 {
     println!("test");
     use std::env;
-    Ok(PgPool::connect(&env::var("DATABASE_URL")?).await?)
+    let pool = PgPool::connect(&env::var("DATABASE_URL")?).await?;
+    run_migrations(&pool).await?;
+    Ok(pool)
 }
 
 // This is a singleton
@@ -29,6 +67,7 @@ where
         Ok(pool.clone())
     } else {
         let pool = PgPool::connect(&config.database_url).await?;
+        run_migrations(&pool).await?;
         if INSTANCE.set(pool).is_err() {
             return Err(Error::DatabaseSingletonError);
         }
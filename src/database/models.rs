@@ -1,11 +1,27 @@
-#[derive(sqlx::Type, Debug, Clone)]
+use crate::models::well_known::{ServerRegistrationStatus, WellKnown};
+use serde::{Deserialize, Serialize};
+use sqlx::types::chrono::{DateTime, Utc};
+use std::collections::HashSet;
+
+#[derive(sqlx::Type, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[sqlx(rename = "registration", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
 pub enum Registration {
     Open,
     Invite,
     Closed,
 }
 
+impl From<&ServerRegistrationStatus> for Registration {
+    fn from(status: &ServerRegistrationStatus) -> Self {
+        match status {
+            ServerRegistrationStatus::Open => Registration::Open,
+            ServerRegistrationStatus::Invite => Registration::Invite,
+            ServerRegistrationStatus::Closed => Registration::Closed,
+        }
+    }
+}
+
 #[derive(Debug, Clone, sqlx::FromRow)]
 pub struct Server {
     pub name: String,
@@ -18,4 +34,129 @@ pub struct Server {
     pub description: String,
     pub registration_status: Registration,
     pub verified: bool,
+    pub registered_by: String,
+    pub verified_by: Option<String>,
+    pub verified_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub last_checked_at: Option<DateTime<Utc>>,
+    pub last_ok_at: Option<DateTime<Utc>>,
+    pub consecutive_failures: i32,
+    pub healthy: bool,
+    /// Copy of the logo in the media repository of the bot's homeserver
+    pub logo_mxc: Option<String>,
+    /// Checklist of the automated tests at the time of the last registration
+    pub check_report: Option<String>,
+    /// Admin who is currently reviewing the pending server
+    pub claimed_by: Option<String>,
+    pub claimed_at: Option<DateTime<Utc>>,
+    /// The message in the admin room asking for verification. Admins can react to it.
+    pub review_event_id: Option<String>,
+}
+
+impl Server {
+    /// Fields which send a listing back to manual verification when they change
+    pub const SENSITIVE_FIELDS: &'static [&'static str] =
+        &["admins", "rules", "registration_status"];
+
+    /// Returns the names of all fields which differ between the stored listing and the well-known file
+    pub fn changed_fields(&self, well_known: &WellKnown) -> Vec<&'static str> {
+        let mut changed = vec![];
+        if self.name != well_known.name {
+            changed.push("name");
+        }
+        if self.url != well_known.url {
+            changed.push("url");
+        }
+        if self.logo_url != well_known.logo_url {
+            changed.push("logo_url");
+        }
+        if self.admins.iter().collect::<HashSet<_>>()
+            != well_known.admins.iter().collect::<HashSet<_>>()
+        {
+            changed.push("admins");
+        }
+        if self.categories.iter().collect::<HashSet<_>>()
+            != well_known.categories.iter().collect::<HashSet<_>>()
+        {
+            changed.push("categories");
+        }
+        if self.rules != well_known.rules {
+            changed.push("rules");
+        }
+        if self.description != well_known.description {
+            changed.push("description");
+        }
+        if self.registration_status != Registration::from(&well_known.registration_status) {
+            changed.push("registration_status");
+        }
+        changed
+    }
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct Rejection {
+    pub id: i32,
+    pub server_name: String,
+    pub reason: String,
+    pub rejected_by: String,
+    pub rejected_at: DateTime<Utc>,
+}
+
+/// Roles of the keymaker team. Every role includes the permissions of the roles before it.
+#[derive(sqlx::Type, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[sqlx(rename = "role", rename_all = "lowercase")]
+pub enum Role {
+    /// May look at the review queue and the review history
+    #[sqlx(rename = "read-only")]
+    ReadOnly,
+    /// May verify and reject pending servers
+    Reviewer,
+    /// May additionally check any server and remove listed servers
+    Moderator,
+    /// May additionally grant and revoke roles
+    Owner,
+}
+
+impl Role {
+    pub const ALL: [Role; 4] = [Role::ReadOnly, Role::Reviewer, Role::Moderator, Role::Owner];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Role::ReadOnly => "read-only",
+            Role::Reviewer => "reviewer",
+            Role::Moderator => "moderator",
+            Role::Owner => "owner",
+        }
+    }
+}
+
+impl std::str::FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Role::ALL
+            .iter()
+            .find(|x| x.name() == s)
+            .copied()
+            .ok_or_else(|| {
+                format!(
+                    "Unknown role '{}'. Valid roles are: {}",
+                    s,
+                    Role::ALL
+                        .iter()
+                        .map(|x| x.name())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+            })
+    }
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct RoleGrant {
+    pub mxid: String,
+    pub role: Role,
+    pub granted_by: String,
+    pub granted_at: DateTime<Utc>,
 }
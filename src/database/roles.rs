@@ -0,0 +1,58 @@
+use super::models::{Role, RoleGrant};
+use crate::errors::Error;
+use sqlx::postgres::PgPool;
+
+/// The role granted to the user, if any
+pub async fn role_of(database: &PgPool, mxid: &str) -> Result<Option<Role>, Error> {
+    let record = sqlx::query!(
+        r#"SELECT role as "role: Role" FROM roles WHERE mxid = $1"#,
+        mxid
+    )
+    .fetch_optional(database)
+    .await?;
+    Ok(record.map(|x| x.role))
+}
+
+/// All granted roles, highest role first
+pub async fn all(database: &PgPool) -> Result<Vec<RoleGrant>, Error> {
+    Ok(sqlx::query_as!(
+        RoleGrant,
+        r#"
+            SELECT mxid, role as "role: Role", granted_by, granted_at
+            FROM roles
+            ORDER BY role DESC, mxid
+        "#
+    )
+    .fetch_all(database)
+    .await?)
+}
+
+/// Grants the role to the user, replacing the role they had before
+pub async fn grant(
+    database: &PgPool,
+    mxid: &str,
+    role: Role,
+    granted_by: &str,
+) -> Result<(), Error> {
+    sqlx::query!(
+        r#"
+            INSERT INTO roles ( mxid, role, granted_by, granted_at )
+            VALUES ( $1, $2, $3, now() )
+            ON CONFLICT (mxid) DO UPDATE SET role = $2, granted_by = $3, granted_at = now()
+        "#,
+        mxid,
+        role as Role,
+        granted_by
+    )
+    .execute(database)
+    .await?;
+    Ok(())
+}
+
+/// Revokes the role of the user. Returns false if the user had no role.
+pub async fn revoke(database: &PgPool, mxid: &str) -> Result<bool, Error> {
+    let result = sqlx::query!(r#"DELETE FROM roles WHERE mxid = $1"#, mxid)
+        .execute(database)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
@@ -0,0 +1,153 @@
+use super::models::{Registration, Rejection, Server};
+use crate::errors::Error;
+use sqlx::postgres::PgPool;
+
+/// The columns of `Server`. New columns only need to be added here and to the struct.
+const SERVER_COLUMNS: &str = "
+    name, url, server_name, logo_url, admins, categories, rules, description, registration_status,
+    verified, registered_by, verified_by, verified_at, created_at, updated_at,
+    last_checked_at, last_ok_at, consecutive_failures, healthy, logo_mxc,
+    check_report, claimed_by, claimed_at, review_event_id
+";
+
+/// Builds a query for servers. `clauses` follows `FROM servers`.
+fn select_servers(clauses: &str) -> String {
+    format!("SELECT {} FROM servers {}", SERVER_COLUMNS, clauses)
+}
+
+/// All servers regardless of their verification state
+pub async fn all(database: &PgPool) -> Result<Vec<Server>, Error> {
+    Ok(
+        sqlx::query_as::<_, Server>(&select_servers("ORDER BY server_name"))
+            .fetch_all(database)
+            .await?,
+    )
+}
+
+/// All verified servers, optionally filtered by category and registration status
+pub async fn verified(
+    database: &PgPool,
+    category: Option<&str>,
+    registration_status: Option<Registration>,
+) -> Result<Vec<Server>, Error> {
+    Ok(sqlx::query_as::<_, Server>(&select_servers(
+        r#"
+            WHERE verified = true
+                AND ($1::text IS NULL OR EXISTS (
+                    SELECT 1 FROM servers_categories
+                    WHERE servers_categories.server_url = servers.url AND servers_categories.category_name = $1
+                ))
+                AND ($2::registration IS NULL OR registration_status = $2)
+            ORDER BY name
+        "#,
+    ))
+    .bind(category)
+    .bind(registration_status)
+    .fetch_all(database)
+    .await?)
+}
+
+/// A single verified server
+pub async fn verified_by_name(
+    database: &PgPool,
+    server_name: &str,
+) -> Result<Option<Server>, Error> {
+    Ok(sqlx::query_as::<_, Server>(&select_servers(
+        "WHERE verified = true AND server_name = $1",
+    ))
+    .bind(server_name)
+    .fetch_optional(database)
+    .await?)
+}
+
+/// All categories used by verified servers together with the number of servers in them
+pub async fn categories(database: &PgPool) -> Result<Vec<(String, i64)>, Error> {
+    let records = sqlx::query!(
+        r#"
+            SELECT servers_categories.category_name, COUNT(*) as "servers!"
+            FROM servers_categories
+            JOIN servers ON servers.url = servers_categories.server_url
+            WHERE servers.verified = true
+            GROUP BY servers_categories.category_name
+            ORDER BY servers_categories.category_name
+        "#
+    )
+    .fetch_all(database)
+    .await?;
+    Ok(records
+        .into_iter()
+        .map(|x| (x.category_name, x.servers))
+        .collect())
+}
+
+/// Verified servers whose name, server name, description or categories contain the query
+pub async fn search(database: &PgPool, query: &str) -> Result<Vec<Server>, Error> {
+    let pattern = format!(
+        "%{}%",
+        query
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_")
+    );
+    Ok(sqlx::query_as::<_, Server>(&select_servers(
+        r#"
+            WHERE verified = true
+                AND (name ILIKE $1 OR server_name ILIKE $1 OR description ILIKE $1 OR EXISTS (
+                    SELECT 1 FROM servers_categories
+                    WHERE servers_categories.server_url = servers.url AND servers_categories.category_name ILIKE $1
+                ))
+            ORDER BY name
+        "#,
+    ))
+    .bind(pattern)
+    .fetch_all(database)
+    .await?)
+}
+
+/// A single server regardless of its verification state
+pub async fn by_name(database: &PgPool, server_name: &str) -> Result<Option<Server>, Error> {
+    Ok(
+        sqlx::query_as::<_, Server>(&select_servers("WHERE server_name = $1"))
+            .bind(server_name)
+            .fetch_optional(database)
+            .await?,
+    )
+}
+
+/// The pending server whose review request in the admin room is the given event
+pub async fn pending_by_review_event(
+    database: &PgPool,
+    event_id: &str,
+) -> Result<Option<Server>, Error> {
+    Ok(sqlx::query_as::<_, Server>(&select_servers(
+        "WHERE verified = false AND review_event_id = $1",
+    ))
+    .bind(event_id)
+    .fetch_optional(database)
+    .await?)
+}
+
+/// Past rejections of a server, newest first
+pub async fn rejections(database: &PgPool, server_name: &str) -> Result<Vec<Rejection>, Error> {
+    Ok(sqlx::query_as!(
+        Rejection,
+        r#"
+            SELECT id, server_name, reason, rejected_by, rejected_at
+            FROM rejections
+            WHERE server_name = $1
+            ORDER BY rejected_at DESC
+        "#,
+        server_name
+    )
+    .fetch_all(database)
+    .await?)
+}
+
+/// All servers waiting for manual verification, oldest first
+pub async fn pending(database: &PgPool) -> Result<Vec<Server>, Error> {
+    Ok(sqlx::query_as::<_, Server>(&select_servers(
+        "WHERE verified = false ORDER BY created_at",
+    ))
+    .fetch_all(database)
+    .await?)
+}
@@ -5,11 +5,86 @@ pub enum Error {
     #[error(transparent)]
     DatabaseError(#[from] sqlx::Error),
     #[error(transparent)]
+    MigrateError(#[from] sqlx::migrate::MigrateError),
+    #[error(transparent)]
+    MatrixError(#[from] matrix_sdk::Error),
+    #[error(transparent)]
     EnvError(#[from] std::env::VarError),
     #[error(transparent)]
+    IoError(#[from] std::io::Error),
+    #[error(transparent)]
+    JsonError(#[from] serde_json::Error),
+    #[error(transparent)]
+    YamlError(#[from] serde_yaml::Error),
+    #[error(transparent)]
+    TemplateError(#[from] tera::Error),
+    #[error(transparent)]
     TokioSendError(
         #[from] tokio::sync::mpsc::error::SendError<matrix_sdk::events::AnyMessageEventContent>,
     ),
+    #[error("Unable to read {field} from {path}: {source}")]
+    SecretFileError {
+        field: &'static str,
+        path: String,
+        source: std::io::Error,
+    },
+    #[error("Invalid value in {variable}: {source}")]
+    EnvOverrideError {
+        variable: String,
+        source: serde_yaml::Error,
+    },
+    #[error("{field}: '{value}' is not a valid URL: {source}")]
+    InvalidUrl {
+        field: &'static str,
+        value: String,
+        source: url::ParseError,
+    },
+    #[error("{field}: '{value}' is not a valid mxid: {source}")]
+    InvalidMxid {
+        field: &'static str,
+        value: String,
+        source: matrix_sdk::identifiers::Error,
+    },
+    #[error("{field}: '{value}' is not a valid room id or alias: {source}")]
+    InvalidRoomId {
+        field: &'static str,
+        value: String,
+        source: matrix_sdk::identifiers::Error,
+    },
+    #[error("{field}: '{value}' is not a valid socket address: {source}")]
+    InvalidAddress {
+        field: &'static str,
+        value: String,
+        source: std::net::AddrParseError,
+    },
+    #[error("{field}: {message}")]
+    InvalidValue {
+        field: &'static str,
+        message: &'static str,
+    },
+    #[error("{field}: '{path}' is not writable: {source}")]
+    PathNotWritable {
+        field: &'static str,
+        path: String,
+        source: std::io::Error,
+    },
+    #[error("database_url: Unable to connect to the database: {source}")]
+    DatabaseUnreachable { source: sqlx::Error },
+    #[error("Found {} problem(s) in the config:\n{}", .0.len(), list_problems(.0))]
+    InvalidConfig(Vec<Error>),
     #[error("Unable to set database singleton")]
     DatabaseSingletonError,
+    #[error("Database schema version {database_version} is newer than the latest migration known to this binary ({binary_version}). Please update the bot.")]
+    SchemaTooNew {
+        database_version: i64,
+        binary_version: i64,
+    },
+}
+
+fn list_problems(problems: &[Error]) -> String {
+    problems
+        .iter()
+        .map(|x| format!("- {}", x))
+        .collect::<Vec<_>>()
+        .join("\n")
 }
@@ -0,0 +1,247 @@
+use crate::api::ServerListing;
+use crate::config::Config;
+use crate::database::{get_database_pool, servers};
+use crate::errors::Error;
+use serde::Serialize;
+use sqlx::types::chrono::{DateTime, Utc};
+use std::cmp::Reverse;
+use std::fs;
+use std::path::Path;
+use tracing::*;
+use url::Url;
+
+/// Version of the JSON format. Bump this on breaking changes so consumers can detect them.
+pub const EXPORT_FORMAT_VERSION: u32 = 1;
+/// Number of newly verified servers listed in the feed
+const FEED_ENTRIES: usize = 50;
+const TITLE: &str = "Keymaker server list";
+const AUTHOR: &str = "Keymaker";
+/// Name of the feed in the export directory, used as its relative self link
+const FEED_FILE: &str = "feed.atom";
+
+#[derive(Serialize)]
+struct Directory<'a> {
+    version: u32,
+    generated_at: DateTime<Utc>,
+    servers: &'a [ServerListing],
+}
+
+/// Writes the verified servers to `servers.json`, `feed.atom` and, if a template is configured, `index.html`
+#[instrument(skip(config))]
+pub async fn run(config: Config<'static>, output: &Path) -> Result<(), Error> {
+    let database = get_database_pool(config.clone()).await?;
+    let listings: Vec<ServerListing> = servers::verified(&database, None, None)
+        .await?
+        .into_iter()
+        .map(Into::into)
+        .collect();
+    let generated_at = Utc::now();
+
+    fs::create_dir_all(output)?;
+
+    let directory = Directory {
+        version: EXPORT_FORMAT_VERSION,
+        generated_at,
+        servers: &listings,
+    };
+    fs::write(
+        output.join("servers.json"),
+        serde_json::to_string_pretty(&directory)?,
+    )?;
+
+    fs::write(output.join(FEED_FILE), atom_feed(&listings, generated_at))?;
+
+    if let Some(ref template) = config.export_template {
+        // Tera only escapes templates whose name ends in .html
+        let mut tera = tera::Tera::default();
+        tera.add_template_file(template.to_string(), Some("export.html"))?;
+        let mut context = tera::Context::new();
+        context.insert("title", TITLE);
+        context.insert("version", &EXPORT_FORMAT_VERSION);
+        context.insert("generated_at", &generated_at);
+        context.insert("servers", &linkable(&listings));
+        fs::write(
+            output.join("index.html"),
+            tera.render("export.html", &context)?,
+        )?;
+    }
+
+    info!(
+        "Exported {} servers to {}",
+        listings.len(),
+        output.display()
+    );
+    Ok(())
+}
+
+/// Removes the links of the listings which are no http(s) URLs, so they can be used in `href` and `src` attributes
+fn linkable(listings: &[ServerListing]) -> Vec<ServerListing> {
+    listings
+        .iter()
+        .cloned()
+        .map(|mut listing| {
+            if !is_web_url(&listing.url) {
+                listing.url = String::new();
+            }
+            listing.logo_url = listing.logo_url.filter(|x| is_web_url(x));
+            listing
+        })
+        .collect()
+}
+
+/// Checks that the URL can not run scripts or point to local files when it is used as a link
+fn is_web_url(url: &str) -> bool {
+    Url::parse(url)
+        .map(|x| x.scheme() == "https" || x.scheme() == "http")
+        .unwrap_or(false)
+}
+
+/// Renders an Atom feed of the most recently verified servers
+fn atom_feed(listings: &[ServerListing], generated_at: DateTime<Utc>) -> String {
+    let mut newest: Vec<&ServerListing> = listings
+        .iter()
+        .filter(|x| x.verified_at.is_some())
+        .collect();
+    newest.sort_by_key(|x| Reverse(x.verified_at));
+    newest.truncate(FEED_ENTRIES);
+    let updated = newest
+        .first()
+        .and_then(|x| x.verified_at)
+        .unwrap_or(generated_at);
+
+    let mut feed = String::new();
+    feed.push_str("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    feed.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    feed.push_str(&format!("  <title>{}</title>\n", TITLE));
+    feed.push_str(&format!("  <author><name>{}</name></author>\n", AUTHOR));
+    feed.push_str(&format!("  <link rel=\"self\" href=\"{}\"/>\n", FEED_FILE));
+    feed.push_str("  <id>urn:keymaker:servers</id>\n");
+    feed.push_str(&format!("  <updated>{}</updated>\n", updated.to_rfc3339()));
+    for listing in newest {
+        let verified_at = listing.verified_at.unwrap_or(listing.updated_at);
+        feed.push_str("  <entry>\n");
+        feed.push_str(&format!(
+            "    <title>{}</title>\n",
+            escape_xml(&listing.name)
+        ));
+        feed.push_str(&format!(
+            "    <id>urn:keymaker:server:{}</id>\n",
+            escape_xml(&listing.server_name)
+        ));
+        let linkable = is_web_url(&listing.url);
+        if linkable {
+            feed.push_str(&format!(
+                "    <link href=\"{}\"/>\n",
+                escape_xml(&listing.url)
+            ));
+        }
+        feed.push_str(&format!(
+            "    <published>{}</published>\n",
+            verified_at.to_rfc3339()
+        ));
+        feed.push_str(&format!(
+            "    <updated>{}</updated>\n",
+            listing.updated_at.max(verified_at).to_rfc3339()
+        ));
+        // An entry without an alternate link has to carry its content
+        if linkable {
+            feed.push_str(&format!(
+                "    <summary>{}</summary>\n",
+                escape_xml(&listing.description)
+            ));
+        } else {
+            feed.push_str(&format!(
+                "    <content type=\"text\">{}</content>\n",
+                escape_xml(&listing.description)
+            ));
+        }
+        for category in &listing.categories {
+            feed.push_str(&format!(
+                "    <category term=\"{}\"/>\n",
+                escape_xml(category)
+            ));
+        }
+        feed.push_str("  </entry>\n");
+    }
+    feed.push_str("</feed>\n");
+    feed
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::models::Registration;
+
+    fn listing(url: &str, logo_url: Option<&str>) -> ServerListing {
+        ServerListing {
+            name: "<b>Example</b>".to_string(),
+            url: url.to_string(),
+            server_name: "example.org".to_string(),
+            logo_url: logo_url.map(ToString::to_string),
+            logo_mxc: None,
+            admins: vec!["@admin:example.org".to_string()],
+            categories: vec!["general".to_string()],
+            rules: "Be nice".to_string(),
+            description: "A \"friendly\" server".to_string(),
+            registration_status: Registration::Open,
+            verified_at: Some(Utc::now()),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn accepts_only_web_urls() {
+        assert!(is_web_url("https://example.org"));
+        assert!(is_web_url("http://example.org/logo.png"));
+        assert!(!is_web_url("javascript:alert(1)"));
+        assert!(!is_web_url("data:text/html,<script>alert(1)</script>"));
+        assert!(!is_web_url("file:///etc/passwd"));
+        assert!(!is_web_url("example.org"));
+    }
+
+    #[test]
+    fn removes_links_which_are_no_web_urls() {
+        let listings = linkable(&[
+            listing("https://example.org", Some("https://example.org/logo.png")),
+            listing("javascript:alert(1)", Some("javascript:alert(1)")),
+        ]);
+        assert_eq!(listings[0].url, "https://example.org");
+        assert_eq!(
+            listings[0].logo_url.as_deref(),
+            Some("https://example.org/logo.png")
+        );
+        assert_eq!(listings[1].url, "");
+        assert_eq!(listings[1].logo_url, None);
+    }
+
+    #[test]
+    fn escapes_the_feed() {
+        let feed = atom_feed(&[listing("https://example.org/?a=1&b=2", None)], Utc::now());
+        assert!(feed.contains("<title>&lt;b&gt;Example&lt;/b&gt;</title>"));
+        assert!(feed.contains("<link href=\"https://example.org/?a=1&amp;b=2\"/>"));
+        assert!(feed.contains("<summary>A &quot;friendly&quot; server</summary>"));
+    }
+
+    #[test]
+    fn names_the_feed_author_and_location() {
+        let feed = atom_feed(&[], Utc::now());
+        assert!(feed.contains("<author><name>Keymaker</name></author>"));
+        assert!(feed.contains("<link rel=\"self\" href=\"feed.atom\"/>"));
+    }
+
+    #[test]
+    fn omits_feed_links_which_are_no_web_urls() {
+        let feed = atom_feed(&[listing("javascript:alert(1)", None)], Utc::now());
+        assert!(!feed.contains("<link href"));
+        assert!(!feed.contains("javascript"));
+        assert!(feed.contains("<content type=\"text\">A &quot;friendly&quot; server</content>"));
+    }
+}
@@ -1,15 +1,26 @@
+use crate::config::Config;
+use crate::errors::Error;
+use crate::render::{escape_html, user_pill};
 use matrix_sdk::{
+    api::r0::room::create_room::{self, RoomPreset},
+    async_trait,
     events::{
+        custom::CustomEventContent,
         room::message::{InReplyTo, MessageEventContent, Relation},
-        AnyMessageEventContent, SyncMessageEvent,
+        AnyMessageEventContent, EventContent, SyncMessageEvent,
     },
-    identifiers::EventId,
-    Room,
+    identifiers::{EventId, RoomId, UserId},
+    Client, Room,
 };
 use tracing::*;
 
 pub trait AnyMessageEventContentExt {
+    /// Marks the message as a reply to the event, without a fallback quoting it
     fn add_relates_to(&mut self, new_relates_to: EventId);
+    /// Marks the message as a reply to the original message, including the fallback quoting it
+    fn add_reply_to(&mut self, room_id: &RoomId, original: &SyncMessageEvent<MessageEventContent>);
+    /// Turns the content into an edit (`m.replace`) of the original event
+    fn into_replacement(self, original: &EventId) -> AnyMessageEventContent;
 }
 
 impl AnyMessageEventContentExt for AnyMessageEventContent {
@@ -20,7 +31,127 @@ impl AnyMessageEventContentExt for AnyMessageEventContent {
                     event_id: new_relates_to,
                 },
             });
+            return;
         }
+        if let AnyMessageEventContent::RoomMessage(MessageEventContent::Text(text)) = self {
+            text.relates_to = Some(Relation::Reply {
+                in_reply_to: InReplyTo {
+                    event_id: new_relates_to,
+                },
+            });
+            return;
+        }
+
+        // Other message types have no typed relation, so it is added to their JSON
+        edit_room_message_json(self, |json| {
+            json["m.relates_to"] = serde_json::json!({
+                "m.in_reply_to": { "event_id": new_relates_to },
+            });
+        });
+    }
+
+    fn add_reply_to(&mut self, room_id: &RoomId, original: &SyncMessageEvent<MessageEventContent>) {
+        let original_json =
+            serde_json::to_value(&original.content).expect("Unable to serialize message content");
+        let original_body = strip_reply_fallback(original_json["body"].as_str().unwrap_or(""));
+        let original_html = match original_json["formatted_body"].as_str() {
+            Some(html) if original_json["format"] == "org.matrix.custom.html" => {
+                strip_html_reply_fallback(html).to_string()
+            }
+            _ => escape_html(&original_body).replace('\n', "<br>"),
+        };
+        let emote = if original_json["msgtype"] == "m.emote" {
+            "* "
+        } else {
+            ""
+        };
+
+        edit_room_message_json(self, |json| {
+            let body = json["body"].as_str().unwrap_or("").to_string();
+            let html = match json["formatted_body"].as_str() {
+                Some(html) if json["format"] == "org.matrix.custom.html" => html.to_string(),
+                _ => escape_html(&body).replace('\n', "<br>"),
+            };
+
+            let mut quote = vec![];
+            for (i, line) in original_body.lines().enumerate() {
+                if i == 0 {
+                    quote.push(format!("> {}<{}> {}", emote, original.sender, line));
+                } else {
+                    quote.push(format!("> {}", line));
+                }
+            }
+            json["body"] = format!("{}\n\n{}", quote.join("\n"), body).into();
+            json["format"] = "org.matrix.custom.html".into();
+            json["formatted_body"] = format!(
+                "<mx-reply><blockquote><a href=\"https://matrix.to/#/{}/{}\">In reply to</a> {}{}<br>{}</blockquote></mx-reply>{}",
+                room_id,
+                original.event_id,
+                emote,
+                user_pill(original.sender.as_str()),
+                original_html,
+                html
+            )
+            .into();
+            json["m.relates_to"] = serde_json::json!({
+                "m.in_reply_to": { "event_id": original.event_id },
+            });
+        });
+    }
+
+    fn into_replacement(self, original: &EventId) -> AnyMessageEventContent {
+        let new_content = serde_json::to_value(&self).expect("Unable to serialize message content");
+
+        // Clients without support for edits show the fallback, which is marked with an asterisk
+        let mut json = new_content.clone();
+        for field in &["body", "formatted_body"] {
+            let fallback = json[*field].as_str().map(|text| format!("* {}", text));
+            if let Some(fallback) = fallback {
+                json[*field] = fallback.into();
+            }
+        }
+        json["m.new_content"] = new_content;
+        json["m.relates_to"] = serde_json::json!({
+            "rel_type": "m.replace",
+            "event_id": original,
+        });
+
+        AnyMessageEventContent::Custom(CustomEventContent {
+            event_type: self.event_type().to_string(),
+            json,
+        })
+    }
+}
+
+/// Replaces a room message with a custom event containing its JSON after applying `edit`
+fn edit_room_message_json(
+    content: &mut AnyMessageEventContent,
+    edit: impl FnOnce(&mut serde_json::Value),
+) {
+    if let AnyMessageEventContent::RoomMessage(_) = content {
+        let event_type = content.event_type().to_string();
+        let mut json =
+            serde_json::to_value(&*content).expect("Unable to serialize message content");
+        edit(&mut json);
+        *content = AnyMessageEventContent::Custom(CustomEventContent { event_type, json });
+    }
+}
+
+/// Removes the quoted original message clients prepend to the body of replies
+pub fn strip_reply_fallback(body: &str) -> String {
+    body.lines()
+        .skip_while(|line| line.starts_with('>'))
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string()
+}
+
+/// Removes the quoted original message clients prepend to the formatted body of replies
+fn strip_html_reply_fallback(html: &str) -> &str {
+    match html.find("</mx-reply>") {
+        Some(index) => &html[index + "</mx-reply>".len()..],
+        None => html,
     }
 }
 
@@ -44,3 +175,105 @@ impl RoomExt for Room {
             .unwrap_or_else(|| event.sender.as_str())
     }
 }
+
+#[async_trait]
+pub trait ClientExt {
+    async fn send_direct_message(
+        &self,
+        user_id: &UserId,
+        content: AnyMessageEventContent,
+    ) -> Result<(), Error>;
+    async fn send_to_admin_room(
+        &self,
+        config: &Config<'_>,
+        content: AnyMessageEventContent,
+    ) -> Option<EventId>;
+}
+
+#[async_trait]
+impl ClientExt for Client {
+    /// Sends the content to the DM with the user. A new DM is created if we do not share one yet.
+    #[instrument(skip(self, content))]
+    async fn send_direct_message(
+        &self,
+        user_id: &UserId,
+        content: AnyMessageEventContent,
+    ) -> Result<(), Error> {
+        let mut direct_room: Option<RoomId> = None;
+        {
+            let joined_rooms = self.joined_rooms();
+            let joined_rooms = joined_rooms.read().await;
+            for (room_id, room) in joined_rooms.iter() {
+                if room.read().await.direct_target.as_ref() == Some(user_id) {
+                    direct_room = Some(room_id.clone());
+                    break;
+                }
+            }
+        }
+
+        let room_id = match direct_room {
+            Some(room_id) => room_id,
+            None => {
+                let invites = [user_id.clone()];
+                let mut request = create_room::Request::new();
+                request.invite = &invites;
+                request.is_direct = true;
+                request.preset = Some(RoomPreset::TrustedPrivateChat);
+                self.create_room(request).await?.room_id
+            }
+        };
+
+        self.room_send(&room_id, content, None).await?;
+        Ok(())
+    }
+
+    /// Sends the content to the admin room. Failures are only logged as nobody else could be told about them.
+    ///
+    /// Returns the id of the sent event.
+    #[instrument(skip(self, config, content))]
+    async fn send_to_admin_room(
+        &self,
+        config: &Config<'_>,
+        content: AnyMessageEventContent,
+    ) -> Option<EventId> {
+        let room_id = match config.admin_room {
+            Some(ref room_id) => room_id,
+            None => {
+                error!("Unable to inform the admin room: not joined yet");
+                return None;
+            }
+        };
+        match self.room_send(room_id, content, None).await {
+            Ok(response) => Some(response.event_id),
+            Err(e) => {
+                error!("Unable to inform the admin room: {}", e);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_the_reply_fallback() {
+        let body = "> <@bot:example.org> Server 'example.org' is waiting for verification\n> with two lines\n\nSpam  \n";
+        assert_eq!(strip_reply_fallback(body), "Spam");
+    }
+
+    #[test]
+    fn keeps_messages_without_fallback() {
+        assert_eq!(strip_reply_fallback("Spam"), "Spam");
+        assert_eq!(
+            strip_reply_fallback("First line\n> quoted later\nlast"),
+            "First line\n> quoted later\nlast"
+        );
+    }
+
+    #[test]
+    fn strips_a_fallback_without_reply() {
+        assert_eq!(strip_reply_fallback("> <@bot:example.org> quote\n"), "");
+    }
+}
@@ -0,0 +1,144 @@
+use crate::config::{Config, SharedConfig};
+use crate::database::models::Server;
+use crate::database::{get_database_pool, servers};
+use crate::errors::Error;
+use crate::extensions::ClientExt;
+use crate::validation::{self, Validation};
+use matrix_sdk::events::{room::message::MessageEventContent, AnyMessageEventContent};
+use sqlx::postgres::PgPool;
+use std::time::Duration;
+use tracing::*;
+
+/// Re-validates the well-known file of every server in the database forever.
+///
+/// This is meant to be spawned next to the sync loop.
+/// The config is read again before every run so reloaded settings apply to the next one.
+pub async fn run(matrix_client: matrix_sdk::Client, config: SharedConfig) {
+    loop {
+        let current = config.read().unwrap().clone();
+        if let Err(e) = check_all(&matrix_client, &current).await {
+            error!("Health check failed: {}", e);
+        }
+        tokio::time::delay_for(Duration::from_secs(current.health_check_interval)).await;
+    }
+}
+
+#[instrument(skip(matrix_client, config))]
+async fn check_all(
+    matrix_client: &matrix_sdk::Client,
+    config: &Config<'static>,
+) -> Result<(), Error> {
+    let database = get_database_pool(config.clone()).await?;
+    let servers = servers::all(&database).await?;
+
+    let client = validation::http_client(config);
+
+    info!("Checking {} servers", servers.len());
+    for server in servers {
+        // A failure with one server must not stop the checks of the others
+        if let Err(e) = check_server(matrix_client, config, &database, &client, &server).await {
+            error!("Health check of {} failed: {}", server.server_name, e);
+        }
+    }
+
+    Ok(())
+}
+
+async fn check_server(
+    matrix_client: &matrix_sdk::Client,
+    config: &Config<'static>,
+    database: &PgPool,
+    client: &reqwest::Client,
+    server: &Server,
+) -> Result<(), Error> {
+    let report = Validation::new(client.clone(), config, &server.server_name, None)
+        .run()
+        .await;
+    match report.well_known {
+        Some(ref well_known) if report.passed() => {
+            sqlx::query!(
+                r#"
+                    UPDATE servers
+                    SET last_checked_at = now(), last_ok_at = now(), consecutive_failures = 0, healthy = true
+                    WHERE server_name = $1
+                "#,
+                server.server_name
+            )
+            .execute(database)
+            .await?;
+
+            if !server.healthy {
+                let content =
+                    AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(
+                        format!("Server {} is healthy again.", server.server_name),
+                    ));
+                matrix_client.send_to_admin_room(config, content).await;
+            }
+
+            let changed: Vec<String> = if server.verified {
+                server
+                    .changed_fields(well_known)
+                    .into_iter()
+                    .map(ToString::to_string)
+                    .collect()
+            } else {
+                vec![]
+            };
+            // Only the first run noticing a (different) set of changed fields sends an alert
+            let outdated_fields_changed = sqlx::query!(
+                r#"
+                    UPDATE servers SET outdated_fields = $2
+                    WHERE server_name = $1 AND outdated_fields IS DISTINCT FROM $2
+                    RETURNING server_name
+                "#,
+                server.server_name,
+                &changed
+            )
+            .fetch_optional(database)
+            .await?
+            .is_some();
+            if outdated_fields_changed && !changed.is_empty() {
+                let content =
+                    AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(format!(
+                        "The listing of {} is out of date. Changed fields: {}. The server admins need to run `!register` again.",
+                        server.server_name,
+                        changed.join(", ")
+                    )));
+                matrix_client.send_to_admin_room(config, content).await;
+            }
+        }
+        _ => {
+            let reason = report.failure().unwrap_or_default();
+            warn!("Health check of {} failed: {}", server.server_name, reason);
+            let record = sqlx::query!(
+                r#"
+                    UPDATE servers
+                    SET last_checked_at = now(), consecutive_failures = consecutive_failures + 1
+                    WHERE server_name = $1
+                    RETURNING consecutive_failures
+                "#,
+                server.server_name
+            )
+            .fetch_one(database)
+            .await?;
+
+            if server.healthy && record.consecutive_failures >= config.health_check_max_failures {
+                sqlx::query!(
+                    r#"UPDATE servers SET healthy = false WHERE server_name = $1"#,
+                    server.server_name
+                )
+                .execute(database)
+                .await?;
+
+                let content =
+                    AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(format!(
+                        "@room Server {} failed {} health checks in a row and was flagged as unhealthy. Last error: {}",
+                        server.server_name, record.consecutive_failures, reason
+                    )));
+                matrix_client.send_to_admin_room(config, content).await;
+            }
+        }
+    }
+
+    Ok(())
+}
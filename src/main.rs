@@ -1,64 +1,162 @@
-use crate::commands::match_command;
-use crate::config::Config;
+use crate::config::{Config, SharedConfig};
+use crate::database::{get_database_pool, models::Role};
+use crate::review::PendingRejections;
 use matrix_sdk::{
     self, async_trait,
     events::{
-        room::member::MemberEventContent, room::message::MessageEventContent, StrippedStateEvent,
-        SyncMessageEvent,
+        room::member::MemberEventContent,
+        room::message::{MessageEventContent, Relation, TextMessageEventContent},
+        StrippedStateEvent, SyncMessageEvent,
     },
-    identifiers::RoomId,
     identifiers::RoomIdOrAliasId,
-    Client, ClientConfig, EventEmitter, Session as SDKSession, SyncRoom, SyncSettings,
+    identifiers::{EventId, RoomId, UserId},
+    Client, ClientConfig, CustomEvent, EventEmitter, Session as SDKSession, SyncRoom, SyncSettings,
 };
-use mrsbfh::config::Loader;
 use mrsbfh::utils::Session;
 use std::convert::TryFrom;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
 use tokio::sync::mpsc;
 use tracing::*;
 use url::Url;
 
+mod api;
 mod commands;
 mod config;
 mod database;
 mod errors;
+mod export;
 mod extensions;
+mod health_check;
 mod models;
+mod reload;
+mod render;
+mod review;
+mod validation;
+
+const CONFIG_PATH: &str = "config.yml";
 
 struct KeybaseBot {
     /// This clone of the `Client` will send requests to the server,
     /// while the other keeps us in sync with the server using `sync`.
     client: Client,
-    config: Config<'static>,
+    config: SharedConfig,
+    /// Rejections started by reacting with ❌ which still wait for their reason
+    pending_rejections: PendingRejections,
 }
 
 impl KeybaseBot {
     #[instrument]
-    pub fn new(client: Client, config: Config<'static>) -> Self {
+    pub fn new(client: Client, config: SharedConfig) -> Self {
         Self {
             client,
-            config: config.clone(),
+            config,
+            pending_rejections: Default::default(),
+        }
+    }
+
+    /// The current config. Handlers take a copy so a reload does not change it halfway through.
+    fn config(&self) -> Config<'static> {
+        self.config.read().unwrap().clone()
+    }
+
+    /// Returns the id of the room if it is the admin room
+    async fn admin_room_id(&self, room: &SyncRoom) -> Option<RoomId> {
+        if let SyncRoom::Joined(ref room) = room {
+            let locked_room = room.read().await;
+            if self.config().admin_room.as_ref() == Some(&locked_room.room_id) {
+                return Some(locked_room.room_id.clone());
+            }
         }
+        None
+    }
+
+    /// Returns the role of the user in the keymaker team
+    async fn role_of(&self, user_id: &UserId) -> Option<Role> {
+        let config = self.config();
+        let role = match get_database_pool(config.clone()).await {
+            Ok(database) => commands::role_of(&config, &database, user_id).await,
+            Err(e) => Err(e),
+        };
+        role.unwrap_or_else(|e| {
+            error!("Unable to look up the role of {}: {}", user_id, e);
+            None
+        })
     }
 }
 
-#[mrsbfh::commands::commands]
 #[mrsbfh::utils::autojoin]
 #[async_trait]
 impl EventEmitter for KeybaseBot {
     async fn on_room_message(&self, room: SyncRoom, event: &SyncMessageEvent<MessageEventContent>) {
-        if let SyncRoom::Joined(ref room) = room {
-            let locked_room = room.read().await;
-            if locked_room.room_id == RoomId::try_from(self.config.admin_room_id.as_ref()).unwrap()
-                && !self
-                    .config
-                    .admins
-                    .iter()
-                    .any(|x| *x == event.sender.to_string())
-            {
+        if let Some(room_id) = self.admin_room_id(&room).await {
+            if self.role_of(&event.sender).await.is_none() {
                 return;
             }
+
+            if let MessageEventContent::Text(TextMessageEventContent {
+                body,
+                relates_to: Some(Relation::Reply { in_reply_to }),
+                ..
+            }) = &event.content
+            {
+                match review::on_reply(
+                    &self.client,
+                    &self.config(),
+                    &self.pending_rejections,
+                    &room_id,
+                    event,
+                    &in_reply_to.event_id,
+                    body,
+                )
+                .await
+                {
+                    Ok(true) => return,
+                    Ok(false) => {}
+                    Err(e) => {
+                        error!("Unable to handle reply: {}", e);
+                        return;
+                    }
+                }
+            }
+        }
+
+        if let SyncRoom::Joined(ref room) = room {
+            let room_id = room.read().await.room_id.clone();
+            commands::dispatch(self.client.clone(), self.config(), room_id, event.clone());
+        }
+    }
+    async fn on_custom_event(&self, room: SyncRoom, event: &CustomEvent<'_>) {
+        let event = match event {
+            CustomEvent::Message(event) if event.content.event_type == "m.reaction" => event,
+            _ => return,
+        };
+        let room_id = match self.admin_room_id(&room).await {
+            Some(room_id) => room_id,
+            None => return,
+        };
+        let relates_to = &event.content.json["m.relates_to"];
+        let reacted_to = relates_to["event_id"]
+            .as_str()
+            .and_then(|event_id| EventId::try_from(event_id).ok());
+        let (reacted_to, key) = match (reacted_to, relates_to["key"].as_str()) {
+            (Some(reacted_to), Some(key)) => (reacted_to, key),
+            _ => return,
+        };
+
+        if let Err(e) = review::on_reaction(
+            &self.client,
+            &self.config(),
+            &self.pending_rejections,
+            &room_id,
+            &event.sender,
+            &reacted_to,
+            key,
+        )
+        .await
+        {
+            error!("Unable to handle reaction: {}", e);
         }
     }
     async fn on_stripped_state_member(
@@ -70,7 +168,7 @@ impl EventEmitter for KeybaseBot {
     }
 }
 
-async fn login_and_sync(config: Config<'static>) -> color_eyre::Result<()> {
+async fn login_and_sync(mut config: Config<'static>) -> color_eyre::Result<()> {
     let store_path_string = config.store_path.to_string();
     let store_path = Path::new(&store_path_string);
     if !store_path.exists() {
@@ -79,18 +177,18 @@ async fn login_and_sync(config: Config<'static>) -> color_eyre::Result<()> {
 
     let client_config = ClientConfig::new().store_path(fs::canonicalize(&store_path)?);
 
-    let homeserver_url =
-        Url::parse(&config.homeserver_url).expect("Couldn't parse the homeserver URL");
+    let homeserver_url = Url::parse(&config.homeserver_url)?;
+    let session_path = PathBuf::from(config.session_path.as_ref());
 
-    let mut client = Client::new_with_config(homeserver_url, client_config).unwrap();
+    let mut client = Client::new_with_config(homeserver_url, client_config)?;
 
-    if let Some(session) = Session::load(config.session_path.parse().unwrap()) {
+    if let Some(session) = Session::load(session_path.clone()) {
         info!("Starting relogin");
 
         let session = SDKSession {
             access_token: session.access_token,
             device_id: session.device_id.into(),
-            user_id: matrix_sdk::identifiers::UserId::try_from(session.user_id.as_str()).unwrap(),
+            user_id: matrix_sdk::identifiers::UserId::try_from(session.user_id.as_str())?,
         };
 
         if let Err(e) = client.restore_login(session).await {
@@ -116,7 +214,7 @@ async fn login_and_sync(config: Config<'static>) -> color_eyre::Result<()> {
                     access_token: login_response.access_token,
                     device_id: login_response.device_id.into(),
                 };
-                session.save(config.session_path.parse().unwrap())?;
+                session.save(session_path)?;
             }
             Err(e) => error!("Error while login: {}", e),
         }
@@ -125,15 +223,26 @@ async fn login_and_sync(config: Config<'static>) -> color_eyre::Result<()> {
 
     println!("logged in as {}", config.mxid);
 
-    client
+    // admin_room_id may be an alias, everything else works with the room id it resolves to
+    let admin_room = client
         .join_room_by_id_or_alias(
             &RoomIdOrAliasId::try_from(config.admin_room_id.as_ref())?,
             &[],
         )
         .await?;
+    config.admin_room = Some(admin_room.room_id);
+
+    let shared_config: SharedConfig = Arc::new(RwLock::new(config.clone()));
+    tokio::spawn(health_check::run(client.clone(), shared_config.clone()));
+    tokio::spawn(api::serve(config));
+    tokio::spawn(reload::watch(
+        client.clone(),
+        shared_config.clone(),
+        CONFIG_PATH.to_string(),
+    ));
 
     client
-        .add_event_emitter(Box::new(KeybaseBot::new(client.clone(), config)))
+        .add_event_emitter(Box::new(KeybaseBot::new(client.clone(), shared_config)))
         .await;
 
     client.sync(SyncSettings::default()).await;
@@ -149,8 +258,40 @@ async fn main() -> color_eyre::Result<()> {
         .with_max_level(tracing::Level::INFO)
         .init();
 
+    dotenv::dotenv().ok();
+
     info!("Loading Configs...");
-    let config = Config::load("config.yml")?;
+    let (config, sources) = config::load(CONFIG_PATH)?;
+    config::log_summary(&config, &sources);
+
+    let mut args = std::env::args().skip(1);
+    let subcommand = args.next();
+    let logs_in = !matches!(
+        subcommand.as_deref(),
+        Some("--migrate-only") | Some("export")
+    );
+
+    info!("Validating Configs...");
+    config::validate(&config, logs_in).await?;
+
+    info!("Migrating database...");
+    get_database_pool(config.clone()).await?;
+
+    match subcommand.as_deref() {
+        Some("--migrate-only") => {
+            info!("Database is up to date. Exiting as requested by --migrate-only");
+            return Ok(());
+        }
+        Some("export") => {
+            let output = args
+                .next()
+                .unwrap_or_else(|| config.export_path.to_string());
+            export::run(config, Path::new(&output)).await?;
+            return Ok(());
+        }
+        _ => {}
+    }
+
     login_and_sync(config).await?;
 
     Ok(())
@@ -0,0 +1,364 @@
+use crate::database::models::{Registration, Rejection, Server};
+use crate::validation::{StepResult, StepStatus};
+use sqlx::types::chrono::{DateTime, Utc};
+
+/// Number of servers shown per message
+pub const PAGE_SIZE: usize = 5;
+
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+pub fn registration_status(status: &Registration) -> &'static str {
+    match status {
+        Registration::Open => "open",
+        Registration::Invite => "invite only",
+        Registration::Closed => "closed",
+    }
+}
+
+/// Returns the items on the 1-based page together with the page actually shown and the number of pages.
+///
+/// Pages out of range are clamped to the first or the last page.
+pub fn paginate<T>(items: &[T], page: usize) -> (&[T], usize, usize) {
+    let pages = ((items.len() + PAGE_SIZE - 1) / PAGE_SIZE).max(1);
+    let page = page.max(1).min(pages);
+    let start = (page - 1) * PAGE_SIZE;
+    (
+        &items[start..(start + PAGE_SIZE).min(items.len())],
+        page,
+        pages,
+    )
+}
+
+/// Renders a paginated list of servers as plain text and HTML
+pub fn server_list(
+    title: &str,
+    servers: &[Server],
+    page: usize,
+    next_page_command: &str,
+) -> (String, String) {
+    let (shown, page, pages) = paginate(servers, page);
+    if shown.is_empty() {
+        let message = format!("{}: No servers found.", title);
+        return (message.clone(), escape_html(&message));
+    }
+
+    let mut plain = format!("{} (page {}/{})\n", title, page, pages);
+    let mut html = format!(
+        "<h4>{} (page {}/{})</h4>\n<ul>\n",
+        escape_html(title),
+        page,
+        pages
+    );
+    for server in shown {
+        plain.push_str(&format!(
+            "\n{} ({}) - Registration: {}\n{}\nCategories: {}\n",
+            server.name,
+            server.server_name,
+            registration_status(&server.registration_status),
+            server.description,
+            server.categories.join(", ")
+        ));
+        html.push_str(&format!(
+            "<li><b>{}</b> (<code>{}</code>) - Registration: {}<br>{}<br><i>Categories: {}</i></li>\n",
+            escape_html(&server.name),
+            escape_html(&server.server_name),
+            registration_status(&server.registration_status),
+            escape_html(&server.description),
+            escape_html(&server.categories.join(", "))
+        ));
+    }
+    html.push_str("</ul>\n");
+
+    if page < pages {
+        let next_page = format!("{} {}", next_page_command, page + 1);
+        plain.push_str(&format!("\nUse `{}` for the next page.", next_page));
+        html.push_str(&format!(
+            "<p>Use <code>{}</code> for the next page.</p>",
+            escape_html(&next_page)
+        ));
+    }
+    (plain, html)
+}
+
+/// Renders the progress of a registration as a checklist in plain text and HTML.
+///
+/// `summary` is appended once all steps finished.
+pub fn registration_progress(
+    server: &str,
+    steps: &[StepResult],
+    total_steps: usize,
+    summary: Option<&str>,
+) -> (String, String) {
+    let title = format!("Verifying {} ({}/{})", server, steps.len(), total_steps);
+    let mut plain = format!("{}\n", title);
+    let mut html = format!("<p><b>{}</b></p>\n<ul>\n", escape_html(&title));
+    for result in steps {
+        let (icon, reason) = match result.status {
+            StepStatus::Passed => ("✅", None),
+            StepStatus::Failed(ref reason) => ("❌", Some(reason)),
+            StepStatus::Skipped(ref reason) => ("⏭", Some(reason)),
+        };
+        match reason {
+            Some(reason) => {
+                plain.push_str(&format!(
+                    "{} {}: {}\n",
+                    icon,
+                    result.step.description(),
+                    reason
+                ));
+                html.push_str(&format!(
+                    "<li>{} {}: <i>{}</i></li>\n",
+                    icon,
+                    escape_html(result.step.description()),
+                    escape_html(reason)
+                ));
+            }
+            None => {
+                plain.push_str(&format!("{} {}\n", icon, result.step.description()));
+                html.push_str(&format!(
+                    "<li>{} {}</li>\n",
+                    icon,
+                    escape_html(result.step.description())
+                ));
+            }
+        }
+    }
+    html.push_str("</ul>\n");
+
+    if let Some(summary) = summary {
+        plain.push_str(&format!("\n{}", summary));
+        html.push_str(&format!("<p>{}</p>", escape_html(summary)));
+    }
+
+    (plain, html)
+}
+
+/// Links the mxid using a matrix.to URL so clients render it as a pill
+pub fn user_pill(mxid: &str) -> String {
+    format!(
+        "<a href=\"https://matrix.to/#/{}\">{}</a>",
+        escape_html(mxid),
+        escape_html(mxid)
+    )
+}
+
+fn health(server: &Server) -> String {
+    match server.last_checked_at {
+        None => "not checked yet".to_string(),
+        Some(last_checked_at) if server.consecutive_failures == 0 => {
+            format!(
+                "ok (checked {})",
+                last_checked_at.format("%Y-%m-%d %H:%M UTC")
+            )
+        }
+        Some(last_checked_at) => format!(
+            "{} ({} failed checks in a row, last check {}, last success {})",
+            if server.healthy {
+                "failing"
+            } else {
+                "unhealthy"
+            },
+            server.consecutive_failures,
+            last_checked_at.format("%Y-%m-%d %H:%M UTC"),
+            server.last_ok_at.map_or_else(
+                || "never".to_string(),
+                |x| x.format("%Y-%m-%d %H:%M UTC").to_string()
+            )
+        ),
+    }
+}
+
+fn verification(server: &Server) -> String {
+    match (server.verified, server.verified_at) {
+        (true, Some(verified_at)) => {
+            format!("verified since {}", verified_at.format("%Y-%m-%d"))
+        }
+        (true, None) => "verified".to_string(),
+        (false, _) => "pending manual verification".to_string(),
+    }
+}
+
+/// Renders all details of a server as plain text and HTML.
+///
+/// With `audit_notes` the internal review history is included, which is meant for admins only.
+pub fn server_card(server: &Server, audit_notes: Option<&[Rejection]>) -> (String, String) {
+    let mut plain = format!(
+        "{} ({})\n{}\n\nRules:\n{}\n\nAdmins: {}\nCategories: {}\nRegistration: {}\nStatus: {}\nHealth: {}\n",
+        server.name,
+        server.server_name,
+        server.description,
+        server.rules,
+        server.admins.join(", "),
+        server.categories.join(", "),
+        registration_status(&server.registration_status),
+        verification(server),
+        health(server)
+    );
+
+    let mut html = String::new();
+    if let Some(ref logo_mxc) = server.logo_mxc {
+        html.push_str(&format!(
+            "<img src=\"{}\" alt=\"\" height=\"64\">\n",
+            escape_html(logo_mxc)
+        ));
+    }
+    html.push_str(&format!(
+        "<h3>{} (<code>{}</code>)</h3>\n<p>{}</p>\n<details><summary>Rules</summary><p>{}</p></details>\n",
+        escape_html(&server.name),
+        escape_html(&server.server_name),
+        escape_html(&server.description),
+        escape_html(&server.rules).replace('\n', "<br>")
+    ));
+    html.push_str(&format!(
+        "<ul>\n<li><b>Admins:</b> {}</li>\n<li><b>Categories:</b> {}</li>\n<li><b>Registration:</b> {}</li>\n<li><b>Status:</b> {}</li>\n<li><b>Health:</b> {}</li>\n</ul>\n",
+        server
+            .admins
+            .iter()
+            .map(|x| user_pill(x))
+            .collect::<Vec<_>>()
+            .join(", "),
+        escape_html(&server.categories.join(", ")),
+        registration_status(&server.registration_status),
+        escape_html(&verification(server)),
+        escape_html(&health(server))
+    ));
+
+    if let Some(rejections) = audit_notes {
+        let mut notes = vec![format!(
+            "Registered by {} on {}",
+            server.registered_by,
+            server.created_at.format("%Y-%m-%d %H:%M UTC")
+        )];
+        if let (Some(ref verified_by), Some(verified_at)) =
+            (&server.verified_by, server.verified_at)
+        {
+            notes.push(format!(
+                "Verified by {} on {}",
+                verified_by,
+                verified_at.format("%Y-%m-%d %H:%M UTC")
+            ));
+        }
+        for rejection in rejections {
+            notes.push(format!(
+                "Rejected by {} on {}: {}",
+                rejection.rejected_by,
+                rejection.rejected_at.format("%Y-%m-%d %H:%M UTC"),
+                rejection.reason
+            ));
+        }
+
+        plain.push_str("\nAudit notes:\n");
+        html.push_str("<h4>Audit notes</h4>\n<ul>\n");
+        for note in notes {
+            plain.push_str(&format!("- {}\n", note));
+            html.push_str(&format!("<li>{}</li>\n", escape_html(&note)));
+        }
+        html.push_str("</ul>\n");
+    }
+
+    (plain, html)
+}
+
+/// Human readable time since the given point in time, e.g. `3 days, 4 hours`
+pub fn age(since: DateTime<Utc>) -> String {
+    let duration = Utc::now() - since;
+    match (duration.num_days(), duration.num_hours() % 24) {
+        (0, 0) => format!("{} minutes", duration.num_minutes()),
+        (0, hours) => format!("{} hours", hours),
+        (days, hours) => format!("{} days, {} hours", days, hours),
+    }
+}
+
+/// The stored listing in the format of the well-known file
+pub fn well_known_json(server: &Server) -> String {
+    let well_known = serde_json::json!({
+        "name": server.name,
+        "url": server.url,
+        "server_name": server.server_name,
+        "logo_url": server.logo_url,
+        "admins": server.admins,
+        "categories": server.categories,
+        "rules": server.rules,
+        "description": server.description,
+        "registration_status": server.registration_status,
+    });
+    serde_json::to_string_pretty(&well_known).unwrap_or_default()
+}
+
+/// Renders one entry of the review queue as plain text and HTML
+pub fn pending_entry(server: &Server) -> (String, String) {
+    let claim = match (&server.claimed_by, server.claimed_at) {
+        (Some(claimed_by), Some(claimed_at)) => {
+            format!("claimed by {} {} ago", claimed_by, age(claimed_at))
+        }
+        _ => "unclaimed".to_string(),
+    };
+    let check_report = server
+        .check_report
+        .as_deref()
+        .unwrap_or("No automated check report stored.");
+    let well_known = well_known_json(server);
+
+    let plain = format!(
+        "{} - submitted {} ago by {}, {}\n{}\n{}\n",
+        server.server_name,
+        age(server.created_at),
+        server.registered_by,
+        claim,
+        well_known,
+        check_report
+    );
+    let html = format!(
+        "<h4>{}</h4>\n<p>Submitted {} ago by {}, {}</p>\n<pre><code class=\"language-json\">{}</code></pre>\n<pre><code>{}</code></pre>\n",
+        escape_html(&server.server_name),
+        age(server.created_at),
+        user_pill(&server.registered_by),
+        escape_html(&claim),
+        escape_html(&well_known),
+        escape_html(check_report)
+    );
+    (plain, html)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_html() {
+        assert_eq!(
+            escape_html(r#"<a href="x">Tom & 'Jerry'</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; &#39;Jerry&#39;&lt;/a&gt;"
+        );
+        assert_eq!(escape_html("&lt;"), "&amp;lt;");
+        assert_eq!(escape_html("plain"), "plain");
+    }
+
+    #[test]
+    fn paginates() {
+        let items: Vec<usize> = (0..12).collect();
+        assert_eq!(paginate(&items, 1), (&items[0..5], 1, 3));
+        assert_eq!(paginate(&items, 2), (&items[5..10], 2, 3));
+        assert_eq!(paginate(&items, 3), (&items[10..12], 3, 3));
+    }
+
+    #[test]
+    fn clamps_out_of_range_pages() {
+        let items: Vec<usize> = (0..12).collect();
+        assert_eq!(paginate(&items, 0), (&items[0..5], 1, 3));
+        assert_eq!(paginate(&items, 9), (&items[10..12], 3, 3));
+    }
+
+    #[test]
+    fn paginates_empty_lists() {
+        let items: Vec<usize> = vec![];
+        assert_eq!(paginate(&items, 1), (&[][..], 1, 1));
+        assert_eq!(paginate(&items, 2), (&[][..], 1, 1));
+    }
+}
@@ -0,0 +1,186 @@
+//! Verifying and rejecting servers by reacting to their review request in the admin room
+use crate::commands::{claimed_by_other, claimed_error, reject_server, role_of, verify_server};
+use crate::config::Config;
+use crate::database::{get_database_pool, models::Role, servers};
+use crate::errors::Error;
+use crate::extensions::{strip_reply_fallback, AnyMessageEventContentExt};
+use crate::render::{escape_html, user_pill};
+use matrix_sdk::{
+    events::{room::message::MessageEventContent, AnyMessageEventContent, SyncMessageEvent},
+    identifiers::{EventId, RoomId, UserId},
+    Client,
+};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::*;
+
+/// Reaction key publishing a server
+const VERIFY_KEY: &str = "✅";
+/// Reaction key starting a rejection
+const REJECT_KEY: &str = "❌";
+
+/// A rejection waiting for the admin to reply with the reason
+pub struct PendingRejection {
+    server_name: String,
+    admin: UserId,
+}
+
+/// Rejections waiting for a reason, keyed by the message asking for it
+pub type PendingRejections = Arc<Mutex<HashMap<EventId, PendingRejection>>>;
+
+/// Handles an admin reacting to a message in the admin room
+#[instrument(skip(client, config, pending_rejections))]
+pub async fn on_reaction(
+    client: &Client,
+    config: &Config<'static>,
+    pending_rejections: &PendingRejections,
+    room_id: &RoomId,
+    sender: &UserId,
+    reacted_to: &EventId,
+    key: &str,
+) -> Result<(), Error> {
+    // Clients may append a variation selector to the emoji
+    let key = key.trim_end_matches('\u{fe0f}');
+    if key != VERIFY_KEY && key != REJECT_KEY {
+        return Ok(());
+    }
+
+    let database = get_database_pool(config.clone()).await?;
+    let server = match servers::pending_by_review_event(&database, reacted_to.as_str()).await? {
+        Some(server) => server,
+        None => return Ok(()),
+    };
+
+    // Only reactions to review requests are checked, everyone may react to other messages
+    if role_of(config, &database, sender).await? < Some(Role::Reviewer) {
+        warn!(
+            user = %sender,
+            "Ignoring review reaction from a user without the reviewer role"
+        );
+        return Ok(());
+    }
+
+    if let Some(claimed_by) = claimed_by_other(&server, sender.as_str()) {
+        let mut content = AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(
+            claimed_error(&server.server_name, claimed_by),
+        ));
+        content.add_relates_to(reacted_to.clone());
+        client.room_send(room_id, content, None).await?;
+        return Ok(());
+    }
+
+    if key == VERIFY_KEY {
+        let verification =
+            verify_server(client, &database, &server.server_name, sender.as_str()).await?;
+        let message = match verification {
+            Some(verification) if verification.notified => format!(
+                "Server '{}' was verified by {} and is now listed.",
+                server.server_name, sender
+            ),
+            Some(verification) => format!(
+                "Server '{}' was verified by {} and is now listed. [ERROR] Unable to notify {} about the verification.",
+                server.server_name, sender, verification.registered_by
+            ),
+            None => format!(
+                "[ERROR] Server '{}' is no longer waiting for verification.",
+                server.server_name
+            ),
+        };
+        let mut content =
+            AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(message));
+        content.add_relates_to(reacted_to.clone());
+        client.room_send(room_id, content, None).await?;
+        return Ok(());
+    }
+
+    let message = format!(
+        "Please reply to this message with the reason for rejecting '{}'. The reason is sent to the server admins.",
+        server.server_name
+    );
+    let mut content = AnyMessageEventContent::RoomMessage(MessageEventContent::notice_html(
+        format!("{}: {}", sender, message),
+        format!("{}: {}", user_pill(sender.as_str()), escape_html(&message)),
+    ));
+    content.add_relates_to(reacted_to.clone());
+    let response = client.room_send(room_id, content, None).await?;
+
+    pending_rejections.lock().await.insert(
+        response.event_id,
+        PendingRejection {
+            server_name: server.server_name,
+            admin: sender.clone(),
+        },
+    );
+
+    Ok(())
+}
+
+/// Handles a reply in the admin room. Returns whether the reply completed a pending rejection.
+#[instrument(skip(client, config, pending_rejections, event, body))]
+pub async fn on_reply(
+    client: &Client,
+    config: &Config<'static>,
+    pending_rejections: &PendingRejections,
+    room_id: &RoomId,
+    event: &SyncMessageEvent<MessageEventContent>,
+    in_reply_to: &EventId,
+    body: &str,
+) -> Result<bool, Error> {
+    let sender = &event.sender;
+    let pending_rejection = {
+        let mut pending_rejections = pending_rejections.lock().await;
+        match pending_rejections.get(in_reply_to) {
+            Some(pending_rejection) if &pending_rejection.admin == sender => {
+                pending_rejections.remove(in_reply_to).unwrap()
+            }
+            _ => return Ok(false),
+        }
+    };
+
+    let reason = strip_reply_fallback(body);
+    if reason.is_empty() {
+        pending_rejections
+            .lock()
+            .await
+            .insert(in_reply_to.clone(), pending_rejection);
+        let mut content = AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(
+            "[ERROR] The reason must not be empty.",
+        ));
+        content.add_reply_to(room_id, event);
+        client.room_send(room_id, content, None).await?;
+        return Ok(true);
+    }
+
+    let database = get_database_pool(config.clone()).await?;
+    let unreachable_admins = reject_server(
+        client,
+        &database,
+        &pending_rejection.server_name,
+        &reason,
+        sender.as_str(),
+        false,
+    )
+    .await?;
+
+    let message = match unreachable_admins {
+        Some(unreachable_admins) if unreachable_admins.is_empty() => format!(
+            "Server '{}' was rejected and removed from the database.",
+            pending_rejection.server_name
+        ),
+        Some(unreachable_admins) => format!(
+            "Server '{}' was rejected and removed from the database. [ERROR] Unable to send the rejection reason to: {:?}",
+            pending_rejection.server_name, unreachable_admins
+        ),
+        None => format!(
+            "[ERROR] Server '{}' is no longer waiting for verification.",
+            pending_rejection.server_name
+        ),
+    };
+    let mut content =
+        AnyMessageEventContent::RoomMessage(MessageEventContent::notice_plain(message));
+    content.add_reply_to(room_id, event);
+    client.room_send(room_id, content, None).await?;
+
+    Ok(true)
+}
@@ -0,0 +1,201 @@
+use tracing::*;
+
+/// Logos larger than this are rejected without downloading the rest
+pub const MAX_LOGO_SIZE: usize = 1024 * 1024;
+pub const MIN_LOGO_DIMENSION: usize = 64;
+pub const MAX_LOGO_DIMENSION: usize = 4096;
+/// Maximum ratio between the longer and the shorter side
+pub const MAX_ASPECT_RATIO: f64 = 2.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogoFormat {
+    Png,
+    Jpeg,
+    Svg,
+    WebP,
+}
+
+impl LogoFormat {
+    pub fn mime(&self) -> mime::Mime {
+        match self {
+            LogoFormat::Png => mime::IMAGE_PNG,
+            LogoFormat::Jpeg => mime::IMAGE_JPEG,
+            LogoFormat::Svg => mime::IMAGE_SVG,
+            LogoFormat::WebP => "image/webp".parse().unwrap(),
+        }
+    }
+
+    /// Detects the format using the magic bytes at the start of the file
+    pub fn detect(bytes: &[u8]) -> Option<Self> {
+        if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+            return Some(LogoFormat::Png);
+        }
+        if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+            return Some(LogoFormat::Jpeg);
+        }
+        if bytes.len() >= 12 && &bytes[0..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+            return Some(LogoFormat::WebP);
+        }
+        let text = String::from_utf8_lossy(&bytes[..bytes.len().min(1024)]);
+        let text = text.trim_start_matches('\u{feff}').trim_start();
+        if (text.starts_with("<?xml") || text.starts_with("<svg") || text.starts_with("<!--"))
+            && text.contains("<svg")
+        {
+            return Some(LogoFormat::Svg);
+        }
+        None
+    }
+}
+
+/// A downloaded logo which passed all checks
+#[derive(Debug, Clone)]
+pub struct Logo {
+    pub format: LogoFormat,
+    pub bytes: Vec<u8>,
+}
+
+/// Downloads the logo, aborting as soon as it exceeds `MAX_LOGO_SIZE`
+pub async fn download(client: &reqwest::Client, logo_url: &str) -> Result<Vec<u8>, String> {
+    let mut resp = client
+        .get(logo_url)
+        .send()
+        .await
+        .map_err(|e| format!("'{}' cannot be reached: {}", logo_url, e))?;
+    if !resp.status().is_success() {
+        return Err(format!(
+            "'{}' returned status code {}",
+            logo_url,
+            resp.status()
+        ));
+    }
+    if let Some(length) = resp.content_length() {
+        if length as usize > MAX_LOGO_SIZE {
+            return Err(format!(
+                "'{}' is {} bytes large. The maximum is {} bytes.",
+                logo_url, length, MAX_LOGO_SIZE
+            ));
+        }
+    }
+
+    let mut bytes = vec![];
+    while let Some(chunk) = resp
+        .chunk()
+        .await
+        .map_err(|e| format!("Unable to download '{}': {}", logo_url, e))?
+    {
+        bytes.extend_from_slice(&chunk);
+        if bytes.len() > MAX_LOGO_SIZE {
+            return Err(format!(
+                "'{}' is larger than the maximum of {} bytes.",
+                logo_url, MAX_LOGO_SIZE
+            ));
+        }
+    }
+    Ok(bytes)
+}
+
+/// Checks format, dimensions and aspect ratio of the downloaded logo
+pub fn check(bytes: Vec<u8>) -> Result<Logo, String> {
+    let format = LogoFormat::detect(&bytes)
+        .ok_or_else(|| "The logo is neither a PNG, JPEG, SVG nor WebP image.".to_string())?;
+
+    // SVGs scale freely so their dimensions do not matter
+    if format != LogoFormat::Svg {
+        let size = imagesize::blob_size(&bytes)
+            .map_err(|e| format!("Unable to read the dimensions of the logo: {:?}", e))?;
+        debug!("Logo is {}x{}", size.width, size.height);
+
+        let shorter_side = size.width.min(size.height);
+        let longer_side = size.width.max(size.height);
+        if shorter_side < MIN_LOGO_DIMENSION || longer_side > MAX_LOGO_DIMENSION {
+            return Err(format!(
+                "The logo is {}x{} pixels. Each side must be between {} and {} pixels.",
+                size.width, size.height, MIN_LOGO_DIMENSION, MAX_LOGO_DIMENSION
+            ));
+        }
+        if longer_side as f64 / shorter_side as f64 > MAX_ASPECT_RATIO {
+            return Err(format!(
+                "The logo is {}x{} pixels. The longer side must not be more than {} times the shorter side.",
+                size.width, size.height, MAX_ASPECT_RATIO
+            ));
+        }
+    }
+
+    Ok(Logo { format, bytes })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The start of a PNG file which is enough to read its dimensions
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut bytes = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        bytes.extend_from_slice(&width.to_be_bytes());
+        bytes.extend_from_slice(&height.to_be_bytes());
+        bytes.extend_from_slice(&[8, 6, 0, 0, 0]);
+        bytes
+    }
+
+    #[test]
+    fn detects_formats_by_magic_bytes() {
+        assert_eq!(LogoFormat::detect(&png(64, 64)), Some(LogoFormat::Png));
+        assert_eq!(
+            LogoFormat::detect(&[0xFF, 0xD8, 0xFF, 0xE0]),
+            Some(LogoFormat::Jpeg)
+        );
+        assert_eq!(
+            LogoFormat::detect(b"RIFF\x24\0\0\0WEBPVP8 "),
+            Some(LogoFormat::WebP)
+        );
+        assert_eq!(
+            LogoFormat::detect(b"\xef\xbb\xbf <?xml version=\"1.0\"?>\n<svg></svg>"),
+            Some(LogoFormat::Svg)
+        );
+        assert_eq!(
+            LogoFormat::detect(b"<svg xmlns=\"http://www.w3.org/2000/svg\"></svg>"),
+            Some(LogoFormat::Svg)
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_formats() {
+        assert_eq!(LogoFormat::detect(b""), None);
+        assert_eq!(LogoFormat::detect(b"GIF89a"), None);
+        assert_eq!(LogoFormat::detect(b"RIFF\x24\0\0\0WAVE"), None);
+        assert_eq!(LogoFormat::detect(b"<?xml version=\"1.0\"?><html/>"), None);
+        assert_eq!(LogoFormat::detect(b"<html><svg></svg></html>"), None);
+        assert!(check(b"GIF89a".to_vec()).is_err());
+    }
+
+    #[test]
+    fn accepts_dimensions_within_the_limits() {
+        for (width, height) in &[(64, 64), (4096, 4096), (128, 64), (2048, 4096)] {
+            let logo = check(png(*width, *height)).unwrap();
+            assert_eq!(logo.format, LogoFormat::Png);
+        }
+    }
+
+    #[test]
+    fn rejects_too_small_or_too_large_logos() {
+        assert_eq!(
+            check(png(63, 64)).unwrap_err(),
+            "The logo is 63x64 pixels. Each side must be between 64 and 4096 pixels."
+        );
+        assert!(check(png(4097, 4096)).is_err());
+    }
+
+    #[test]
+    fn rejects_extreme_aspect_ratios() {
+        assert_eq!(
+            check(png(64, 129)).unwrap_err(),
+            "The logo is 64x129 pixels. The longer side must not be more than 2 times the shorter side."
+        );
+    }
+
+    #[test]
+    fn ignores_dimensions_of_svgs() {
+        let logo = check(b"<svg width=\"1\" height=\"1\"></svg>".to_vec()).unwrap();
+        assert_eq!(logo.format, LogoFormat::Svg);
+    }
+}
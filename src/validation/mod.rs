@@ -0,0 +1,401 @@
+use crate::config::Config;
+use crate::models::well_known::{ServerRegistrationStatus, WellKnown};
+use probes::AdvertisedRegistration;
+use reqwest::StatusCode;
+use resolver::{DnsSrvResolver, Resolver, ServerName};
+use std::time::{Duration, Instant};
+use tracing::*;
+
+pub mod logo;
+pub mod probes;
+pub mod resolver;
+pub mod schema;
+
+/// The automated checks a well-known file has to pass, in the order they are run
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Step {
+    Fetch,
+    Status,
+    Format,
+    Content,
+    Admin,
+    ServerName,
+    Url,
+    Registration,
+    Logo,
+}
+
+impl Step {
+    pub const ALL: [Step; 9] = [
+        Step::Fetch,
+        Step::Status,
+        Step::Format,
+        Step::Content,
+        Step::Admin,
+        Step::ServerName,
+        Step::Url,
+        Step::Registration,
+        Step::Logo,
+    ];
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Step::Fetch => "Getting well-known file",
+            Step::Status => "Ensuring .well-known file is reachable",
+            Step::Format => "Ensuring .well-known file is valid",
+            Step::Content => "Ensuring .well-known file content is sensible",
+            Step::Admin => "Ensuring .well-known file has you listed as an admin of the server",
+            Step::ServerName => {
+                "Ensuring .well-known file server_name field speaks the Matrix federation API"
+            }
+            Step::Url => "Ensuring .well-known file url field speaks the Matrix client-server API",
+            Step::Registration => {
+                "Ensuring .well-known file registration_status matches what the server advertises"
+            }
+            Step::Logo => "Ensuring .well-known file logo_url field is a usable image",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StepStatus {
+    Passed,
+    Failed(String),
+    Skipped(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct StepResult {
+    pub step: Step,
+    pub status: StepStatus,
+    pub duration: Duration,
+}
+
+#[derive(Debug)]
+pub struct Report {
+    pub well_known_url: String,
+    pub well_known: Option<WellKnown>,
+    /// The downloaded logo if the logo check passed
+    pub logo: Option<logo::Logo>,
+    pub steps: Vec<StepResult>,
+}
+
+impl Report {
+    /// True as long as no step failed
+    pub fn passed(&self) -> bool {
+        self.failure().is_none()
+    }
+
+    /// The message of the first failed step
+    pub fn failure(&self) -> Option<&str> {
+        self.steps.iter().find_map(|x| match x.status {
+            StepStatus::Failed(ref message) => Some(message.as_str()),
+            _ => None,
+        })
+    }
+
+    /// Renders all steps as a plain text checklist, one line per step
+    pub fn checklist(&self) -> String {
+        self.steps
+            .iter()
+            .map(|x| match x.status {
+                StepStatus::Passed => format!(
+                    "[PASSED] {} ({} ms)",
+                    x.step.description(),
+                    x.duration.as_millis()
+                ),
+                StepStatus::Failed(ref reason) => {
+                    format!("[FAILED] {}: {}", x.step.description(), reason)
+                }
+                StepStatus::Skipped(ref reason) => {
+                    format!("[SKIPPED] {}: {}", x.step.description(), reason)
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Creates the http client used for all checks
+pub fn http_client(config: &Config<'_>) -> reqwest::Client {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(config.check_timeout))
+        .build()
+        .expect("Unable to build http client")
+}
+
+/// Runs the automated checks against the well-known file of a server.
+///
+/// Steps are run one at a time using `next_step` so callers can report progress.
+/// After the first failure all remaining steps are skipped.
+pub struct Validation {
+    client: reqwest::Client,
+    resolver: Resolver,
+    server_name: Result<ServerName, String>,
+    expected_admin: Option<String>,
+    allowed_categories: Vec<String>,
+    probe_registration: bool,
+    client_base_url: Option<String>,
+    response: Option<reqwest::Response>,
+    report: Report,
+}
+
+impl Validation {
+    /// `expected_admin` is the mxid which has to be listed in the admins of the well-known file.
+    /// The check is skipped if it is `None`.
+    pub fn new(
+        client: reqwest::Client,
+        config: &Config<'_>,
+        server: &str,
+        expected_admin: Option<&str>,
+    ) -> Self {
+        let server_name = server.parse::<ServerName>();
+        let well_known_url = match server_name {
+            Ok(ref server_name) => server_name.well_known_url("mx.homeservers.metadata"),
+            Err(_) => format!(
+                "https://{}/.well-known/matrix/mx.homeservers.metadata",
+                server
+            ),
+        };
+        Validation {
+            resolver: Resolver::new(client.clone(), DnsSrvResolver::shared()),
+            client,
+            server_name,
+            expected_admin: expected_admin.map(ToString::to_string),
+            allowed_categories: config.categories.iter().map(|x| x.to_string()).collect(),
+            probe_registration: config.probe_registration,
+            client_base_url: None,
+            response: None,
+            report: Report {
+                well_known_url,
+                well_known: None,
+                logo: None,
+                steps: vec![],
+            },
+        }
+    }
+
+    /// Runs the next step. Returns `None` once all steps are done.
+    pub async fn next_step(&mut self) -> Option<&StepResult> {
+        let step = *Step::ALL.get(self.report.steps.len())?;
+        let start = Instant::now();
+        let status = if self.report.passed() {
+            self.run_step(step).await
+        } else {
+            StepStatus::Skipped("A previous step failed.".to_string())
+        };
+        self.report.steps.push(StepResult {
+            step,
+            status,
+            duration: start.elapsed(),
+        });
+        self.report.steps.last()
+    }
+
+    /// Runs all remaining steps
+    pub async fn run(mut self) -> Report {
+        while self.next_step().await.is_some() {}
+        self.report
+    }
+
+    pub fn into_report(self) -> Report {
+        self.report
+    }
+
+    #[instrument(skip(self))]
+    async fn run_step(&mut self, step: Step) -> StepStatus {
+        let well_known_url = &self.report.well_known_url;
+        match step {
+            Step::Fetch => {
+                if let Err(ref e) = self.server_name {
+                    return StepStatus::Failed(format!("Invalid server name: {}", e));
+                }
+                match self.client.get(well_known_url).send().await {
+                    Ok(resp) => {
+                        self.response = Some(resp);
+                        StepStatus::Passed
+                    }
+                    Err(e) => {
+                        debug!("Error fetching well-known: {:?}", e);
+                        StepStatus::Failed(format!(
+                            "Unable to find well_known file at: '{}'. This is most likely due to a connectivity issue.",
+                            well_known_url
+                        ))
+                    }
+                }
+            }
+            Step::Status => {
+                let status = self
+                    .response
+                    .as_ref()
+                    .map(|resp| resp.status())
+                    .expect("Status checked without response");
+                if status == StatusCode::OK {
+                    StepStatus::Passed
+                } else {
+                    StepStatus::Failed(format!(
+                        ".well-known file at: '{}' returned incorrect status code {}. We expect Status Code 200.",
+                        well_known_url, status
+                    ))
+                }
+            }
+            Step::Format => {
+                let resp = self
+                    .response
+                    .take()
+                    .expect("Format checked without response");
+                let text = match resp.text().await {
+                    Ok(text) => text,
+                    Err(e) => {
+                        return StepStatus::Failed(format!(
+                            "Unable to read .well-known file at: '{}': {}",
+                            well_known_url, e
+                        ))
+                    }
+                };
+                match schema::parse(&text) {
+                    Ok(well_known) => {
+                        self.report.well_known = Some(well_known);
+                        StepStatus::Passed
+                    }
+                    Err(e) => {
+                        debug!("Error parsing json: {}", e);
+                        StepStatus::Failed(format!(
+                            ".well-known file at: '{}' has invalid format. {}",
+                            well_known_url, e
+                        ))
+                    }
+                }
+            }
+            Step::Content => {
+                let well_known = self.report.well_known.as_ref().unwrap();
+                let problems = schema::check_content(well_known, &self.allowed_categories);
+                if problems.is_empty() {
+                    StepStatus::Passed
+                } else {
+                    StepStatus::Failed(format!(
+                        ".well-known file at: '{}' has invalid content:\n{}",
+                        well_known_url,
+                        problems.join("\n")
+                    ))
+                }
+            }
+            Step::Admin => {
+                let well_known = self.report.well_known.as_ref().unwrap();
+                match self.expected_admin {
+                    None => StepStatus::Skipped("No admin to look for was given.".to_string()),
+                    Some(ref admin) if well_known.admins.iter().any(|x| x == admin) => {
+                        StepStatus::Passed
+                    }
+                    Some(ref admin) => StepStatus::Failed(format!(
+                        "According to the .well-known file at: '{}' you are not any of the admins of this homeserver. Your mxid: {}, Admins in the .well-known config: {:?}",
+                        well_known_url, admin, well_known.admins
+                    )),
+                }
+            }
+            Step::ServerName => {
+                let well_known = self.report.well_known.as_ref().unwrap();
+                let server_name = match well_known.server_name.parse::<ServerName>() {
+                    Ok(server_name) => server_name,
+                    Err(e) => {
+                        return StepStatus::Failed(format!(
+                            "The server_name field from the .well-known file is invalid: {}",
+                            e
+                        ))
+                    }
+                };
+                let endpoint = self.resolver.resolve_federation(&server_name).await;
+                match probes::federation_version(&self.client, &endpoint.base_url()).await {
+                    Ok(version) => {
+                        debug!("{} runs {}", well_known.server_name, version);
+                        StepStatus::Passed
+                    }
+                    Err(e) => StepStatus::Failed(format!(
+                        "The server_name field from the .well-known file ('{}') does not speak the Matrix federation API: {}",
+                        well_known.server_name, e
+                    )),
+                }
+            }
+            Step::Url => {
+                let well_known = self.report.well_known.as_ref().unwrap();
+                let url = match ServerName::from_url_field(&well_known.url) {
+                    Ok(url) => url,
+                    Err(e) => {
+                        return StepStatus::Failed(format!(
+                            "The url field from the .well-known file is invalid: {}",
+                            e
+                        ))
+                    }
+                };
+                let url_address = self.resolver.resolve_client(&url).await;
+                let versions = probes::client_versions(&self.client, &url_address).await;
+                match versions {
+                    Ok(versions) => {
+                        debug!("{} supports {:?}", url_address, versions);
+                        self.client_base_url = Some(url_address);
+                        StepStatus::Passed
+                    }
+                    Err(e) => StepStatus::Failed(format!(
+                        "The url field from the .well-known file ('{}') does not speak the Matrix client-server API: {}",
+                        well_known.url, e
+                    )),
+                }
+            }
+            Step::Registration => {
+                if !self.probe_registration {
+                    return StepStatus::Skipped("Registration probe is disabled.".to_string());
+                }
+                let well_known = self.report.well_known.as_ref().unwrap();
+                let base_url = self.client_base_url.as_ref().unwrap();
+                let advertised = match probes::registration(&self.client, base_url).await {
+                    Ok(advertised) => advertised,
+                    Err(e) => {
+                        return StepStatus::Failed(format!(
+                            "Unable to find out if registration is possible: {}",
+                            e
+                        ))
+                    }
+                };
+                let matches = match well_known.registration_status {
+                    ServerRegistrationStatus::Open => advertised == AdvertisedRegistration::Open,
+                    ServerRegistrationStatus::Invite => advertised != AdvertisedRegistration::Open,
+                    ServerRegistrationStatus::Closed => {
+                        advertised == AdvertisedRegistration::Disabled
+                    }
+                };
+                if matches {
+                    StepStatus::Passed
+                } else {
+                    StepStatus::Failed(format!(
+                        "The registration_status field from the .well-known file is {:?} but the server advertises {:?}.",
+                        well_known.registration_status, advertised
+                    ))
+                }
+            }
+            Step::Logo => {
+                let well_known = self.report.well_known.as_ref().unwrap();
+                let logo_url = match well_known.logo_url {
+                    None => {
+                        return StepStatus::Skipped(
+                            "Skipping check as no logo_url was defined.".to_string(),
+                        )
+                    }
+                    Some(ref logo_url) => logo_url,
+                };
+                let checked_logo = logo::download(&self.client, logo_url)
+                    .await
+                    .and_then(logo::check);
+                match checked_logo {
+                    Ok(checked_logo) => {
+                        self.report.logo = Some(checked_logo);
+                        StepStatus::Passed
+                    }
+                    Err(e) => StepStatus::Failed(format!(
+                        "The logo_url field from the .well-known file ('{}') is not a usable logo: {}",
+                        logo_url, e
+                    )),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use serde::Deserialize;
+
+/// Stage which has to be completed when registration requires a token
+const REGISTRATION_TOKEN_STAGES: &[&str] = &[
+    "m.login.registration_token",
+    "org.matrix.msc3231.login.registration_token",
+];
+
+#[derive(Deserialize)]
+struct ClientVersions {
+    versions: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct FederationVersion {
+    server: FederationServer,
+}
+
+#[derive(Deserialize)]
+struct FederationServer {
+    name: Option<String>,
+    version: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RegistrationFlows {
+    flows: Vec<RegistrationFlow>,
+}
+
+#[derive(Deserialize)]
+struct RegistrationFlow {
+    #[serde(default)]
+    stages: Vec<String>,
+}
+
+/// What a server advertises when asked to register a new account
+#[derive(Debug, Clone, PartialEq)]
+pub enum AdvertisedRegistration {
+    Open,
+    TokenRequired,
+    Disabled,
+}
+
+/// Returns the client-server API versions supported by the homeserver at `base_url`
+pub async fn client_versions(
+    client: &reqwest::Client,
+    base_url: &str,
+) -> Result<Vec<String>, String> {
+    let versions_url = format!("{}/_matrix/client/versions", base_url);
+    let resp = client
+        .get(&versions_url)
+        .send()
+        .await
+        .map_err(|e| format!("'{}' cannot be reached: {}", versions_url, e))?;
+    if !resp.status().is_success() {
+        return Err(format!(
+            "'{}' returned status code {}",
+            versions_url,
+            resp.status()
+        ));
+    }
+    let versions = resp
+        .json::<ClientVersions>()
+        .await
+        .map_err(|e| format!("'{}' did not return a version list: {}", versions_url, e))?;
+    if versions.versions.is_empty() {
+        return Err(format!("'{}' does not list any versions", versions_url));
+    }
+    Ok(versions.versions)
+}
+
+/// Returns the server implementation as reported by the federation API at `base_url`
+pub async fn federation_version(
+    client: &reqwest::Client,
+    base_url: &str,
+) -> Result<String, String> {
+    let version_url = format!("{}/_matrix/federation/v1/version", base_url);
+    let resp = client
+        .get(&version_url)
+        .send()
+        .await
+        .map_err(|e| format!("'{}' cannot be reached: {}", version_url, e))?;
+    if !resp.status().is_success() {
+        return Err(format!(
+            "'{}' returned status code {}",
+            version_url,
+            resp.status()
+        ));
+    }
+    let version = resp
+        .json::<FederationVersion>()
+        .await
+        .map_err(|e| format!("'{}' did not return a server version: {}", version_url, e))?;
+    Ok(format!(
+        "{} {}",
+        version.server.name.unwrap_or_else(|| "unknown".to_string()),
+        version.server.version.unwrap_or_default()
+    ))
+}
+
+/// Starts a registration without any data to find out which flows the server offers
+pub async fn registration(
+    client: &reqwest::Client,
+    base_url: &str,
+) -> Result<AdvertisedRegistration, String> {
+    let register_url = format!("{}/_matrix/client/r0/register", base_url);
+    let resp = client
+        .post(&register_url)
+        .body("{}")
+        .send()
+        .await
+        .map_err(|e| format!("'{}' cannot be reached: {}", register_url, e))?;
+    match resp.status().as_u16() {
+        401 => {
+            let flows = resp.json::<RegistrationFlows>().await.map_err(|e| {
+                format!(
+                    "'{}' did not return registration flows: {}",
+                    register_url, e
+                )
+            })?;
+            let token_required = !flows.flows.is_empty()
+                && flows.flows.iter().all(|flow| {
+                    flow.stages
+                        .iter()
+                        .any(|stage| REGISTRATION_TOKEN_STAGES.contains(&stage.as_str()))
+                });
+            if token_required {
+                Ok(AdvertisedRegistration::TokenRequired)
+            } else {
+                Ok(AdvertisedRegistration::Open)
+            }
+        }
+        403 => Ok(AdvertisedRegistration::Disabled),
+        status => Err(format!(
+            "'{}' returned unexpected status code {}",
+            register_url, status
+        )),
+    }
+}
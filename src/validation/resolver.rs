@@ -0,0 +1,502 @@
+use async_trait::async_trait;
+use once_cell::sync::{Lazy, OnceCell};
+use serde::Deserialize;
+use std::fmt;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+use std::sync::Arc;
+use tracing::*;
+use trust_dns_resolver::TokioAsyncResolver;
+
+/// Port used for federation if neither the server name nor any delegation specifies one
+pub const DEFAULT_FEDERATION_PORT: u16 = 8448;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Host {
+    Ipv4(Ipv4Addr),
+    Ipv6(Ipv6Addr),
+    Domain(String),
+}
+
+impl fmt::Display for Host {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Host::Ipv4(ip) => write!(f, "{}", ip),
+            Host::Ipv6(ip) => write!(f, "[{}]", ip),
+            Host::Domain(domain) => write!(f, "{}", domain),
+        }
+    }
+}
+
+impl FromStr for Host {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(ip) = s.parse::<Ipv4Addr>() {
+            return Ok(Host::Ipv4(ip));
+        }
+        let is_valid_domain = !s.is_empty()
+            && s.len() <= 255
+            && s.chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.');
+        if is_valid_domain {
+            Ok(Host::Domain(s.to_string()))
+        } else {
+            Err(format!("'{}' is not a valid hostname", s))
+        }
+    }
+}
+
+/// A server name as defined by the Matrix specification: a hostname, IPv4 or IPv6 literal with optional port
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerName {
+    pub host: Host,
+    pub port: Option<u16>,
+}
+
+impl ServerName {
+    /// Parses a field which may either be a bare server name or an https URL without path
+    pub fn from_url_field(url: &str) -> Result<Self, String> {
+        let url = url.trim_end_matches('/');
+        url.strip_prefix("https://").unwrap_or(url).parse()
+    }
+
+    /// The URL of a file below `/.well-known/matrix/` for this server.
+    ///
+    /// Like the spec mandates for delegation files these are always served on the default https port.
+    pub fn well_known_url(&self, file: &str) -> String {
+        format!("https://{}/.well-known/matrix/{}", self.host, file)
+    }
+}
+
+impl fmt::Display for ServerName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.port {
+            Some(port) => write!(f, "{}:{}", self.host, port),
+            None => write!(f, "{}", self.host),
+        }
+    }
+}
+
+impl FromStr for ServerName {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (host, port) = if let Some(rest) = s.strip_prefix('[') {
+            let end = rest
+                .find(']')
+                .ok_or_else(|| format!("'{}' contains an unterminated IPv6 literal", s))?;
+            let ip = rest[..end]
+                .parse::<Ipv6Addr>()
+                .map_err(|e| format!("'{}' contains an invalid IPv6 literal: {}", s, e))?;
+            (Host::Ipv6(ip), &rest[end + 1..])
+        } else {
+            match s.rfind(':') {
+                Some(index) => (s[..index].parse()?, &s[index..]),
+                None => (s.parse()?, ""),
+            }
+        };
+
+        let port = if port.is_empty() {
+            None
+        } else {
+            let port = port
+                .strip_prefix(':')
+                .ok_or_else(|| format!("'{}' has trailing characters after the host", s))?;
+            Some(
+                port.parse::<u16>()
+                    .map_err(|e| format!("'{}' has an invalid port: {}", s, e))?,
+            )
+        };
+
+        Ok(ServerName { host, port })
+    }
+}
+
+/// The host and port federation requests for a server are sent to
+#[derive(Debug, Clone, PartialEq)]
+pub struct Endpoint {
+    pub host: Host,
+    pub port: u16,
+}
+
+impl Endpoint {
+    pub fn base_url(&self) -> String {
+        format!("https://{}:{}", self.host, self.port)
+    }
+}
+
+#[async_trait]
+pub trait SrvResolver: Send + Sync {
+    /// Looks up a SRV record and returns the target and port with the highest priority
+    async fn lookup(&self, name: &str) -> Option<(String, u16)>;
+}
+
+/// Resolves SRV records using the system DNS configuration
+#[derive(Default)]
+pub struct DnsSrvResolver {
+    /// Created on the first lookup and reused afterwards
+    resolver: OnceCell<TokioAsyncResolver>,
+}
+
+impl DnsSrvResolver {
+    /// The resolver shared by all validations
+    pub fn shared() -> Arc<dyn SrvResolver> {
+        static INSTANCE: Lazy<Arc<DnsSrvResolver>> = Lazy::new(Default::default);
+        INSTANCE.clone()
+    }
+
+    async fn resolver(&self) -> Option<&TokioAsyncResolver> {
+        if self.resolver.get().is_none() {
+            match TokioAsyncResolver::tokio_from_system_conf().await {
+                Ok(resolver) => {
+                    // Another lookup may have been faster, its resolver is used then
+                    let _ = self.resolver.set(resolver);
+                }
+                Err(e) => {
+                    error!("Unable to create DNS resolver: {}", e);
+                    return None;
+                }
+            }
+        }
+        self.resolver.get()
+    }
+}
+
+#[async_trait]
+impl SrvResolver for DnsSrvResolver {
+    async fn lookup(&self, name: &str) -> Option<(String, u16)> {
+        let lookup = self.resolver().await?.srv_lookup(name).await.ok()?;
+        lookup.iter().min_by_key(|x| x.priority()).map(|x| {
+            (
+                x.target().to_utf8().trim_end_matches('.').to_string(),
+                x.port(),
+            )
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct ServerWellKnown {
+    #[serde(rename = "m.server")]
+    server: String,
+}
+
+#[derive(Deserialize)]
+struct ClientWellKnown {
+    #[serde(rename = "m.homeserver")]
+    homeserver: HomeserverInfo,
+}
+
+#[derive(Deserialize)]
+struct HomeserverInfo {
+    base_url: String,
+}
+
+/// Resolves server names to the addresses of their federation and client-server APIs
+#[derive(Clone)]
+pub struct Resolver {
+    client: reqwest::Client,
+    srv: Arc<dyn SrvResolver>,
+}
+
+impl Resolver {
+    pub fn new(client: reqwest::Client, srv: Arc<dyn SrvResolver>) -> Self {
+        Resolver { client, srv }
+    }
+
+    /// Resolves where federation requests for the server name are sent to, following the server discovery rules of the spec
+    #[instrument(skip(self))]
+    pub async fn resolve_federation(&self, server_name: &ServerName) -> Endpoint {
+        let delegated = match (&server_name.host, server_name.port) {
+            (Host::Domain(_), None) => self.fetch_delegation(server_name).await,
+            _ => None,
+        };
+        self.follow_delegation(server_name, delegated).await
+    }
+
+    /// Applies the remaining discovery rules once `/.well-known/matrix/server` was looked at
+    async fn follow_delegation(
+        &self,
+        server_name: &ServerName,
+        delegated: Option<ServerName>,
+    ) -> Endpoint {
+        if let (Host::Domain(ref hostname), None) = (&server_name.host, server_name.port) {
+            if let Some(delegated) = delegated {
+                debug!("{} is delegated to {}", server_name, delegated);
+                if let (Host::Domain(ref delegated_hostname), None) =
+                    (&delegated.host, delegated.port)
+                {
+                    if let Some(endpoint) = self.lookup_srv(delegated_hostname).await {
+                        return endpoint;
+                    }
+                }
+                return Endpoint {
+                    host: delegated.host,
+                    port: delegated.port.unwrap_or(DEFAULT_FEDERATION_PORT),
+                };
+            }
+            if let Some(endpoint) = self.lookup_srv(hostname).await {
+                return endpoint;
+            }
+        }
+        Endpoint {
+            host: server_name.host.clone(),
+            port: server_name.port.unwrap_or(DEFAULT_FEDERATION_PORT),
+        }
+    }
+
+    /// Resolves the base URL of the client-server API using `/.well-known/matrix/client`
+    #[instrument(skip(self))]
+    pub async fn resolve_client(&self, server_name: &ServerName) -> String {
+        if let Ok(resp) = self
+            .client
+            .get(&server_name.well_known_url("client"))
+            .send()
+            .await
+        {
+            if resp.status().is_success() {
+                if let Ok(well_known) = resp.json::<ClientWellKnown>().await {
+                    return well_known
+                        .homeserver
+                        .base_url
+                        .trim_end_matches('/')
+                        .to_string();
+                }
+            }
+        }
+        format!("https://{}", server_name)
+    }
+
+    async fn fetch_delegation(&self, server_name: &ServerName) -> Option<ServerName> {
+        let resp = self
+            .client
+            .get(&server_name.well_known_url("server"))
+            .send()
+            .await
+            .ok()?;
+        if !resp.status().is_success() {
+            return None;
+        }
+        let body = resp.text().await.ok()?;
+        parse_delegation(&body)
+            .map_err(|e| warn!("Invalid delegation for {}: {}", server_name, e))
+            .ok()
+    }
+
+    async fn lookup_srv(&self, hostname: &str) -> Option<Endpoint> {
+        let (target, port) = self
+            .srv
+            .lookup(&format!("_matrix._tcp.{}", hostname))
+            .await?;
+        Some(Endpoint {
+            host: target.parse().ok()?,
+            port,
+        })
+    }
+}
+
+/// Reads the server name from a `/.well-known/matrix/server` file
+fn parse_delegation(body: &str) -> Result<ServerName, String> {
+    let well_known = serde_json::from_str::<ServerWellKnown>(body).map_err(|e| e.to_string())?;
+    well_known.server.parse()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// Answers SRV lookups from a fixed table instead of asking DNS
+    struct FakeSrvResolver(HashMap<&'static str, (&'static str, u16)>);
+
+    #[async_trait]
+    impl SrvResolver for FakeSrvResolver {
+        async fn lookup(&self, name: &str) -> Option<(String, u16)> {
+            self.0
+                .get(name)
+                .map(|(target, port)| (target.to_string(), *port))
+        }
+    }
+
+    fn resolver(records: &[(&'static str, (&'static str, u16))]) -> Resolver {
+        Resolver::new(
+            reqwest::Client::new(),
+            Arc::new(FakeSrvResolver(records.iter().cloned().collect())),
+        )
+    }
+
+    fn server_name(s: &str) -> ServerName {
+        s.parse().unwrap()
+    }
+
+    fn endpoint_at(host: &str, port: u16) -> Endpoint {
+        Endpoint {
+            host: host.parse().unwrap(),
+            port,
+        }
+    }
+
+    #[test]
+    fn parses_hostnames_with_and_without_port() {
+        assert_eq!(
+            server_name("example.org"),
+            ServerName {
+                host: Host::Domain("example.org".to_string()),
+                port: None,
+            }
+        );
+        assert_eq!(
+            server_name("matrix.example.org:8008"),
+            ServerName {
+                host: Host::Domain("matrix.example.org".to_string()),
+                port: Some(8008),
+            }
+        );
+    }
+
+    #[test]
+    fn parses_ip_literals() {
+        assert_eq!(
+            server_name("1.2.3.4:1234"),
+            ServerName {
+                host: Host::Ipv4(Ipv4Addr::new(1, 2, 3, 4)),
+                port: Some(1234),
+            }
+        );
+        assert_eq!(
+            server_name("[1234:5678::abcd]"),
+            ServerName {
+                host: Host::Ipv6("1234:5678::abcd".parse().unwrap()),
+                port: None,
+            }
+        );
+        assert_eq!(
+            server_name("[::1]:8448"),
+            ServerName {
+                host: Host::Ipv6(Ipv6Addr::LOCALHOST),
+                port: Some(8448),
+            }
+        );
+    }
+
+    #[test]
+    fn formats_like_it_was_parsed() {
+        for s in &["example.org", "example.org:8448", "1.2.3.4", "[::1]:8448"] {
+            assert_eq!(server_name(s).to_string(), *s);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_server_names() {
+        for s in &[
+            "",
+            "example.org:",
+            "example.org:port",
+            "example.org:65536",
+            "exa mple.org",
+            "::1",
+            "[::1",
+            "[::1]8448",
+            "[not-an-ip]",
+            "https://example.org/path",
+        ] {
+            assert!(s.parse::<ServerName>().is_err(), "{} was accepted", s);
+        }
+    }
+
+    #[test]
+    fn parses_url_fields() {
+        assert_eq!(
+            ServerName::from_url_field("https://matrix.example.org/").unwrap(),
+            server_name("matrix.example.org")
+        );
+        assert_eq!(
+            ServerName::from_url_field("matrix.example.org:443").unwrap(),
+            server_name("matrix.example.org:443")
+        );
+    }
+
+    #[test]
+    fn parses_delegation_files() {
+        assert_eq!(
+            parse_delegation(r#"{"m.server": "matrix.example.org:443"}"#).unwrap(),
+            server_name("matrix.example.org:443")
+        );
+        assert!(parse_delegation(r#"{"m.server": "not a server"}"#).is_err());
+        assert!(parse_delegation(r#"{"m.homeserver": "matrix.example.org"}"#).is_err());
+        assert!(parse_delegation("<html></html>").is_err());
+    }
+
+    #[tokio::test]
+    async fn uses_the_port_of_a_delegation() {
+        let resolver = resolver(&[("_matrix._tcp.example.org", ("srv.example.org", 1234))]);
+        let endpoint = resolver
+            .follow_delegation(
+                &server_name("example.org"),
+                Some(server_name("matrix.example.org:443")),
+            )
+            .await;
+        assert_eq!(endpoint, endpoint_at("matrix.example.org", 443));
+    }
+
+    #[tokio::test]
+    async fn looks_up_srv_records_of_the_delegated_hostname() {
+        let resolver = resolver(&[
+            ("_matrix._tcp.example.org", ("wrong.example.org", 1)),
+            ("_matrix._tcp.matrix.example.org", ("srv.example.org", 1234)),
+        ]);
+        let endpoint = resolver
+            .follow_delegation(
+                &server_name("example.org"),
+                Some(server_name("matrix.example.org")),
+            )
+            .await;
+        assert_eq!(endpoint, endpoint_at("srv.example.org", 1234));
+    }
+
+    #[tokio::test]
+    async fn uses_the_default_port_for_a_delegation_without_srv_record() {
+        let endpoint = resolver(&[])
+            .follow_delegation(
+                &server_name("example.org"),
+                Some(server_name("matrix.example.org")),
+            )
+            .await;
+        assert_eq!(
+            endpoint,
+            endpoint_at("matrix.example.org", DEFAULT_FEDERATION_PORT)
+        );
+    }
+
+    #[tokio::test]
+    async fn looks_up_srv_records_without_delegation() {
+        let resolver = resolver(&[("_matrix._tcp.example.org", ("srv.example.org", 1234))]);
+        let endpoint = resolver
+            .follow_delegation(&server_name("example.org"), None)
+            .await;
+        assert_eq!(endpoint, endpoint_at("srv.example.org", 1234));
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_the_server_name() {
+        let endpoint = resolver(&[])
+            .follow_delegation(&server_name("example.org"), None)
+            .await;
+        assert_eq!(
+            endpoint,
+            endpoint_at("example.org", DEFAULT_FEDERATION_PORT)
+        );
+    }
+
+    #[tokio::test]
+    async fn ignores_delegation_and_srv_records_for_explicit_ports_and_ips() {
+        let resolver = resolver(&[("_matrix._tcp.example.org", ("srv.example.org", 1234))]);
+        let endpoint = resolver
+            .resolve_federation(&server_name("example.org:8008"))
+            .await;
+        assert_eq!(endpoint, endpoint_at("example.org", 8008));
+        let endpoint = resolver.resolve_federation(&server_name("1.2.3.4")).await;
+        assert_eq!(endpoint, endpoint_at("1.2.3.4", DEFAULT_FEDERATION_PORT));
+    }
+}
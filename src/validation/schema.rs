@@ -0,0 +1,246 @@
+use crate::models::well_known::WellKnown;
+use matrix_sdk::identifiers::UserId;
+use serde_path_to_error::Segment;
+use std::convert::TryFrom;
+use url::Url;
+
+pub const MAX_DESCRIPTION_LENGTH: usize = 1000;
+pub const MAX_RULES_LENGTH: usize = 10000;
+
+/// Parses the well-known file.
+///
+/// On failure the error names the offending field using its serde path and includes the value found there.
+pub fn parse(text: &str) -> Result<WellKnown, String> {
+    let value: serde_json::Value =
+        serde_json::from_str(text).map_err(|e| format!("The file is not valid JSON: {}", e))?;
+
+    serde_path_to_error::deserialize::<_, WellKnown>(value.clone()).map_err(|e| {
+        let path = e.path();
+        let pointer: String = path
+            .iter()
+            .map(|segment| match segment {
+                Segment::Seq { index } => format!("/{}", index),
+                Segment::Map { key } => format!("/{}", key),
+                Segment::Enum { variant } => format!("/{}", variant),
+                Segment::Unknown => "/?".to_string(),
+            })
+            .collect();
+        match value.pointer(&pointer) {
+            Some(found) if !pointer.is_empty() => format!(
+                "Field '{}' is invalid: {}. Found value: {}",
+                path,
+                e.inner(),
+                found
+            ),
+            _ => format!("Field '{}' is invalid: {}", path, e.inner()),
+        }
+    })
+}
+
+/// Checks the content of an already parsed well-known file. Returns all problems found.
+pub fn check_content(well_known: &WellKnown, allowed_categories: &[String]) -> Vec<String> {
+    let mut problems = vec![];
+
+    if well_known.name.trim().is_empty() {
+        problems.push("Field 'name' must not be empty.".to_string());
+    }
+    if well_known.description.trim().is_empty() {
+        problems.push("Field 'description' must not be empty.".to_string());
+    }
+    if well_known.description.chars().count() > MAX_DESCRIPTION_LENGTH {
+        problems.push(format!(
+            "Field 'description' must not be longer than {} characters.",
+            MAX_DESCRIPTION_LENGTH
+        ));
+    }
+    if well_known.rules.chars().count() > MAX_RULES_LENGTH {
+        problems.push(format!(
+            "Field 'rules' must not be longer than {} characters.",
+            MAX_RULES_LENGTH
+        ));
+    }
+
+    if well_known.admins.is_empty() {
+        problems.push("Field 'admins' must list at least one admin.".to_string());
+    }
+    for (index, admin) in well_known.admins.iter().enumerate() {
+        match UserId::try_from(admin.as_str()) {
+            Ok(user_id) if user_id.server_name().as_str() != well_known.server_name => problems
+                .push(format!(
+                    "Field 'admins[{}]' ('{}') is not a user of '{}'.",
+                    index, admin, well_known.server_name
+                )),
+            Ok(_) => {}
+            Err(e) => problems.push(format!(
+                "Field 'admins[{}]' ('{}') is not a valid mxid: {}",
+                index, admin, e
+            )),
+        }
+    }
+
+    for (index, category) in well_known.categories.iter().enumerate() {
+        if !allowed_categories.contains(category) {
+            problems.push(format!(
+                "Field 'categories[{}]' ('{}') is not a known category. Allowed categories: {}",
+                index,
+                category,
+                allowed_categories.join(", ")
+            ));
+        }
+        if well_known.categories[..index].contains(category) {
+            problems.push(format!(
+                "Field 'categories[{}]' ('{}') is listed more than once.",
+                index, category
+            ));
+        }
+    }
+
+    if let Some(ref logo_url) = well_known.logo_url {
+        match Url::parse(logo_url) {
+            Ok(url) if url.scheme() != "https" => {
+                problems.push(format!("Field 'logo_url' ('{}') must use https.", logo_url))
+            }
+            Ok(_) => {}
+            Err(e) => problems.push(format!(
+                "Field 'logo_url' ('{}') is not a valid URL: {}",
+                logo_url, e
+            )),
+        }
+    }
+
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::well_known::ServerRegistrationStatus;
+
+    fn well_known() -> WellKnown {
+        WellKnown {
+            name: "Example".to_string(),
+            url: "https://example.org".to_string(),
+            server_name: "example.org".to_string(),
+            logo_url: Some("https://example.org/logo.png".to_string()),
+            admins: vec!["@admin:example.org".to_string()],
+            categories: vec!["general".to_string()],
+            rules: "Be nice".to_string(),
+            description: "A friendly server".to_string(),
+            registration_status: ServerRegistrationStatus::Open,
+        }
+    }
+
+    fn categories() -> Vec<String> {
+        vec!["general".to_string(), "tech".to_string()]
+    }
+
+    #[test]
+    fn accepts_valid_content() {
+        assert!(check_content(&well_known(), &categories()).is_empty());
+    }
+
+    #[test]
+    fn rejects_empty_name_and_description() {
+        let mut well_known = well_known();
+        well_known.name = " ".to_string();
+        well_known.description = String::new();
+
+        let problems = check_content(&well_known, &categories());
+        assert_eq!(problems.len(), 2);
+        assert!(problems[0].contains("'name'"));
+        assert!(problems[1].contains("'description'"));
+    }
+
+    #[test]
+    fn counts_characters_instead_of_bytes() {
+        let mut well_known = well_known();
+        well_known.description = "ä".repeat(MAX_DESCRIPTION_LENGTH);
+        well_known.rules = "ä".repeat(MAX_RULES_LENGTH);
+        assert!(check_content(&well_known, &categories()).is_empty());
+
+        well_known.description.push('ä');
+        well_known.rules.push('ä');
+        let problems = check_content(&well_known, &categories());
+        assert_eq!(problems.len(), 2);
+        assert!(problems[0].contains("'description' must not be longer"));
+        assert!(problems[1].contains("'rules' must not be longer"));
+    }
+
+    #[test]
+    fn checks_admins() {
+        let mut well_known = well_known();
+        well_known.admins = vec![];
+        assert_eq!(
+            check_content(&well_known, &categories()),
+            vec!["Field 'admins' must list at least one admin.".to_string()]
+        );
+
+        well_known.admins = vec![
+            "@admin:example.org".to_string(),
+            "@admin:example.com".to_string(),
+            "admin".to_string(),
+        ];
+        let problems = check_content(&well_known, &categories());
+        assert_eq!(problems.len(), 2);
+        assert_eq!(
+            problems[0],
+            "Field 'admins[1]' ('@admin:example.com') is not a user of 'example.org'."
+        );
+        assert!(problems[1].starts_with("Field 'admins[2]' ('admin') is not a valid mxid"));
+    }
+
+    #[test]
+    fn rejects_unknown_categories() {
+        let mut well_known = well_known();
+        well_known.categories = vec!["tech".to_string(), "cats".to_string()];
+        assert_eq!(
+            check_content(&well_known, &categories()),
+            vec![
+                "Field 'categories[1]' ('cats') is not a known category. Allowed categories: general, tech"
+                    .to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_duplicate_categories() {
+        let mut well_known = well_known();
+        well_known.categories = vec!["tech".to_string(), "tech".to_string()];
+        assert_eq!(
+            check_content(&well_known, &categories()),
+            vec!["Field 'categories[1]' ('tech') is listed more than once.".to_string()]
+        );
+    }
+
+    #[test]
+    fn requires_https_logo_url() {
+        let mut well_known = well_known();
+        well_known.logo_url = None;
+        assert!(check_content(&well_known, &categories()).is_empty());
+
+        well_known.logo_url = Some("http://example.org/logo.png".to_string());
+        assert_eq!(
+            check_content(&well_known, &categories()),
+            vec!["Field 'logo_url' ('http://example.org/logo.png') must use https.".to_string()]
+        );
+
+        well_known.logo_url = Some("logo.png".to_string());
+        let problems = check_content(&well_known, &categories());
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].starts_with("Field 'logo_url' ('logo.png') is not a valid URL"));
+    }
+
+    #[test]
+    fn names_the_invalid_field() {
+        let error = parse(r#"{"name": "Example", "admins": "@admin:example.org"}"#).unwrap_err();
+        assert!(error.starts_with("Field 'admins' is invalid"), "{}", error);
+        assert!(
+            error.ends_with("Found value: \"@admin:example.org\""),
+            "{}",
+            error
+        );
+
+        let error = parse("{").unwrap_err();
+        assert!(error.starts_with("The file is not valid JSON"), "{}", error);
+    }
+}